@@ -1,6 +1,7 @@
 use crate::runtime::*;
 use crate::syntax_tree::*;
-use std::collections::HashMap;
+use crate::util::CRange;
+use std::collections::{HashMap, HashSet};
 use std::ptr::NonNull;
 
 struct OffsetInfo {
@@ -72,21 +73,23 @@ impl OffsetTable {
     }
 }
 
+fn compare_op(op: CmpOp) -> Opcode {
+    return match op {
+        CmpOp::Eq => Opcode::Equal,
+        CmpOp::Ne => Opcode::NotEqual,
+        CmpOp::Lt => Opcode::Less,
+        CmpOp::Le => Opcode::LessEqual,
+        CmpOp::Gt => Opcode::Greater,
+        CmpOp::Ge => Opcode::GreaterEqual,
+    };
+}
+
 #[derive(Debug, Clone, Copy)]
 struct OpLoc {
     pub function_index: u32,
     pub offset: u32,
 }
 
-#[derive(Clone, Copy)]
-struct FuncInfo<'a> {
-    uid: u32,
-    argument_uids: &'a [u32],
-    declarations: &'a [Declaration],
-    stmts: &'a [TStmt<'a>],
-    parent: &'a OffsetTable,
-}
-
 #[derive(Debug, Clone, Copy)]
 enum AsmContext {
     Function {
@@ -111,10 +114,42 @@ impl AsmContext {
     }
 }
 
+// Where `break` and `continue` jump to in the innermost loop
+#[derive(Debug, Clone, Copy)]
+struct LoopLabels {
+    exit: u32,
+    next: u32,
+}
+
 pub struct Assembler {
     functions: HashMap<u32, Vec<Opcode>>,
     function_names: HashMap<u32, u32>,
     labels: Vec<OpLoc>,
+    strings: Vec<String>,
+    string_ids: HashMap<String, u32>,
+    locations: Vec<(OpLoc, CRange)>,
+    // Start address and uid of every function, global code included
+    function_starts: Vec<(u32, u32)>,
+    function_addresses: HashMap<u32, u32>,
+    // Frame offsets of the globals declared by assembled entries
+    globals: HashMap<u32, u32>,
+    // Type index of the instances of each class, by the class's uid
+    class_type_indices: HashMap<u32, u32>,
+    // Names and uids of each class's methods, by the class's uid
+    class_methods: HashMap<u32, Vec<(u32, u32)>>,
+    // Interfaces are numbered by their method names, since that's all their
+    // itables depend on
+    interface_ids: HashMap<Vec<u32>, u32>,
+    // Class and interface of every itable that's been asked for, and the
+    // method names of the ones that haven't been emitted yet
+    itables: HashSet<(u32, u32)>,
+    pending_itables: Vec<(u32, u32, Vec<u32>)>,
+    // Interfaces that values of another interface get used as, which every
+    // class with an itable for the first needs one for too
+    interface_upcasts: HashSet<(u32, u32)>,
+    strings_emitted: usize,
+    // Source location of every op that can fail at runtime, by address
+    pub source_map: HashMap<u32, CRange>,
 }
 
 impl Assembler {
@@ -123,9 +158,106 @@ impl Assembler {
             functions: HashMap::new(),
             function_names: HashMap::new(),
             labels: Vec::new(),
+            strings: Vec::new(),
+            string_ids: HashMap::new(),
+            locations: Vec::new(),
+            function_starts: Vec::new(),
+            function_addresses: HashMap::new(),
+            globals: HashMap::new(),
+            class_type_indices: HashMap::new(),
+            class_methods: HashMap::new(),
+            interface_ids: HashMap::new(),
+            itables: HashSet::new(),
+            pending_itables: Vec::new(),
+            interface_upcasts: HashSet::new(),
+            strings_emitted: 0,
+            source_map: HashMap::new(),
         };
     }
 
+    pub fn add_string(&mut self, value: &str) -> u32 {
+        if let Some(&id) = self.string_ids.get(value) {
+            return id;
+        }
+
+        let id = self.strings.len() as u32;
+        self.strings.push(String::from(value));
+        self.string_ids.insert(String::from(value), id);
+        return id;
+    }
+
+    // Each string added since the last call becomes a BeginStringData op
+    // holding its length in bytes, followed by the bytes packed into
+    // StringData words.
+    fn assemble_data(&mut self) -> Vec<Opcode> {
+        let mut data = Vec::new();
+        for string in &self.strings[self.strings_emitted..] {
+            let bytes = string.as_bytes();
+            data.push(Opcode::BeginStringData(bytes.len() as u64));
+            for chunk in bytes.chunks(8) {
+                let mut word = [0u8; 8];
+                word[..chunk.len()].copy_from_slice(chunk);
+                data.push(Opcode::StringData(u64::from_ne_bytes(word)));
+            }
+        }
+
+        self.strings_emitted = self.strings.len();
+        return data;
+    }
+
+    // Each itable asked for since the last call becomes a BeginItable op,
+    // followed by the uids of the methods in ItableEntry ops, which get
+    // replaced with their addresses when linking.
+    fn assemble_itables(&mut self) -> Vec<Opcode> {
+        let mut changed = true;
+        while changed {
+            changed = false;
+            let mut itables: Vec<(u32, u32)> = self.itables.iter().copied().collect();
+            let mut upcasts: Vec<(u32, u32)> = self.interface_upcasts.iter().copied().collect();
+            itables.sort();
+            upcasts.sort();
+            for &(class, interface) in &itables {
+                for &(from, to) in &upcasts {
+                    if from == interface && self.itables.insert((class, to)) {
+                        let names = self.interface_names(to);
+                        self.pending_itables.push((class, to, names));
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        let mut data = Vec::new();
+        for (class, interface, names) in std::mem::take(&mut self.pending_itables) {
+            data.push(Opcode::BeginItable {
+                type_index: self.class_type_index(class),
+                interface,
+            });
+            for name in names {
+                let methods = &self.class_methods[&class];
+                let &(_, uid) = methods.iter().find(|&&(n, _)| n == name).unwrap();
+                data.push(Opcode::ItableEntry(uid));
+            }
+        }
+        return data;
+    }
+
+    fn class_type_index(&mut self, uid: u32) -> u32 {
+        let next = FIRST_INSTANCE_TYPE_INDEX + self.class_type_indices.len() as u32;
+        return *self.class_type_indices.entry(uid).or_insert(next);
+    }
+
+    fn interface_names(&self, id: u32) -> Vec<u32> {
+        let (names, _) = self.interface_ids.iter().find(|&(_, &i)| i == id).unwrap();
+        return names.clone();
+    }
+
+    fn interface_id(&mut self, interface: &[InterfaceMethod]) -> u32 {
+        let names: Vec<u32> = interface.iter().map(|m| m.name).collect();
+        let next = self.interface_ids.len() as u32;
+        return *self.interface_ids.entry(names).or_insert(next);
+    }
+
     pub fn create_label(&mut self, function_index: u32) -> u32 {
         let idx = self.labels.len() as u32;
         self.labels.push(OpLoc {
@@ -139,6 +271,32 @@ impl Assembler {
         self.labels[label_idx as usize].offset = location;
     }
 
+    // Name of the function whose code contains `address`, or None if it's in
+    // the global code
+    pub fn function_name_at(&self, address: u32) -> Option<u32> {
+        let idx = self
+            .function_starts
+            .partition_point(|&(start, _)| start <= address);
+        let (_, uid) = self.function_starts[idx.checked_sub(1)?];
+        return self.function_names.get(&uid).copied();
+    }
+
+    pub fn is_function_start(&self, address: u32) -> bool {
+        return self
+            .function_starts
+            .binary_search_by_key(&address, |&(start, _)| start)
+            .is_ok();
+    }
+
+    // Records `view` as the source of the next op pushed onto `ops`
+    fn mark_source(&mut self, context: AsmContext, ops: &[Opcode], view: CRange) {
+        let location = OpLoc {
+            function_index: context.func_idx(),
+            offset: ops.len() as u32,
+        };
+        self.locations.push((location, view));
+    }
+
     pub fn assemble_program(&mut self, program_tree: TProgram) -> Vec<Opcode> {
         let mut program = Vec::new();
         let mut offsets = OffsetTable::new_global();
@@ -163,19 +321,73 @@ impl Assembler {
         );
 
         program.push(Opcode::Return);
+        return self.link(program, 0);
+    }
+
+    // Assembles one piece of top-level code that runs after everything
+    // assembled before it, to be placed at address `base`. The global frame
+    // is left to the caller, which has to grow it to `global_frame_size()`
+    // slots before running the code. Execution halts at the end instead of
+    // returning, so the global frame stays on the stack.
+    pub fn assemble_entry(&mut self, program_tree: TProgram, base: u32) -> Vec<Opcode> {
+        for decl in program_tree.declarations {
+            if !self.globals.contains_key(&decl.name) {
+                let offset = self.globals.len() as u32 + 1;
+                self.globals.insert(decl.name, offset);
+            }
+        }
+
+        let mut offsets = OffsetTable::new_global();
+        offsets.uids = self.globals.clone();
+
+        let mut program = Vec::new();
+        self.assemble_block(
+            AsmContext::Global,
+            None,
+            &mut program,
+            offsets,
+            program_tree.stmts,
+        );
+
+        program.push(Opcode::Halt);
+        return self.link(program, base);
+    }
+
+    pub fn global_frame_size(&self) -> u32 {
+        return self.globals.len() as u32 + 1;
+    }
+
+    // Lays out the data segment, then the global code, then every function
+    // assembled since the last call, and resolves their addresses.
+    fn link(&mut self, mut program: Vec<Opcode>, base: u32) -> Vec<Opcode> {
+        let mut data = self.assemble_itables();
+        data.append(&mut self.assemble_data());
+        let data_len = base + data.len() as u32;
 
-        let mut function_translations = HashMap::new();
-        function_translations.insert(0, 0);
+        self.function_addresses.insert(0, data_len);
+        self.function_starts.push((data_len, 0));
 
-        for (id, mut stmts) in self.functions.drain() {
-            let function_offset = program.len() as u32;
-            function_translations.insert(id, function_offset);
+        let mut functions: Vec<(u32, Vec<Opcode>)> = self.functions.drain().collect();
+        functions.sort_by_key(|&(id, _)| id);
+        for (id, mut stmts) in functions {
+            let function_offset = data_len + program.len() as u32;
+            self.function_addresses.insert(id, function_offset);
+            self.function_starts.push((function_offset, id));
             program.append(&mut stmts);
         }
+        self.function_starts.sort();
 
-        for op in &mut program {
+        let function_translations = &self.function_addresses;
+        for (op_loc, view) in self.locations.drain(..) {
+            let address = function_translations[&op_loc.function_index] + op_loc.offset;
+            self.source_map.insert(address, view);
+        }
+
+        for op in data.iter_mut().chain(&mut program) {
             match op {
-                Opcode::Call(func) => *func = function_translations[func],
+                Opcode::Call(func) | Opcode::FunctionAddress(func) | Opcode::ItableEntry(func) => {
+                    *func = function_translations[func]
+                }
                 Opcode::JumpNotIf(label) => {
                     let op_loc = self.labels[*label as usize];
                     *label = function_translations[&op_loc.function_index] + op_loc.offset;
@@ -188,12 +400,17 @@ impl Assembler {
                     let op_loc = self.labels[*label as usize];
                     *label = function_translations[&op_loc.function_index] + op_loc.offset;
                 }
+                Opcode::ForRange(label) => {
+                    let op_loc = self.labels[*label as usize];
+                    *label = function_translations[&op_loc.function_index] + op_loc.offset;
+                }
 
                 _ => {}
             }
         }
 
-        return program;
+        data.append(&mut program);
+        return data;
     }
 
     fn assemble_function(
@@ -262,20 +479,14 @@ impl Assembler {
     fn assemble_block<'a>(
         &mut self,
         context: AsmContext,
-        loop_label: Option<u32>,
+        loop_labels: Option<LoopLabels>,
         current: &mut Vec<Opcode>,
         offsets: OffsetTable,
         stmts: &'a [TStmt<'a>],
     ) {
         for stmt in stmts {
             match stmt {
-                TStmt::Function {
-                    uid,
-                    name,
-                    argument_names,
-                    declarations,
-                    stmts,
-                } => {
+                TStmt::Function { uid, name, .. } => {
                     self.function_names.insert(*uid, *name);
                     current.push(Opcode::GetLocal { stack_offset: 0 });
                     current.push(Opcode::GetLocal { stack_offset: 0 });
@@ -283,6 +494,44 @@ impl Assembler {
                         offset: offsets.search_current(*name),
                     });
                 }
+                // Classes are objects holding the frame their methods were
+                // defined in and the methods' addresses
+                TStmt::Class { uid, name, methods } => {
+                    let mut names = Vec::new();
+                    for method in methods.iter() {
+                        if let TStmt::Function { uid, name, .. } = method {
+                            names.push((*name, *uid));
+                        }
+                    }
+                    self.class_methods.insert(*uid, names);
+
+                    current.push(Opcode::HeapAlloc {
+                        header: ObjectHeader {
+                            type_index: CLASS_TYPE_INDEX,
+                            object_size: methods.len() as u32 + 1,
+                        },
+                    });
+                    current.push(Opcode::Dup);
+                    current.push(Opcode::GetLocal { stack_offset: 0 });
+                    current.push(Opcode::Swap);
+                    current.push(Opcode::HeapWrite { offset: 0 });
+                    for (idx, method) in methods.iter().enumerate() {
+                        if let TStmt::Function { uid, name, .. } = method {
+                            self.function_names.insert(*uid, *name);
+                            current.push(Opcode::Dup);
+                            current.push(Opcode::FunctionAddress(*uid));
+                            current.push(Opcode::Swap);
+                            current.push(Opcode::HeapWrite {
+                                offset: idx as u32 + 1,
+                            });
+                        }
+                    }
+
+                    current.push(Opcode::GetLocal { stack_offset: 0 });
+                    current.push(Opcode::HeapWrite {
+                        offset: offsets.search_current(*name),
+                    });
+                }
                 _ => {}
             }
         }
@@ -290,24 +539,27 @@ impl Assembler {
         for stmt in stmts {
             match stmt {
                 TStmt::Expr(expr) => {
-                    self.convert_expression_to_ops(current, &offsets, expr);
+                    self.convert_expression_to_ops(context, current, &offsets, expr);
                     current.push(Opcode::Pop);
                 }
                 TStmt::Assign { to, value } => {
-                    self.convert_expression_to_ops(current, &offsets, value);
-                    let info = offsets.search(*to);
-                    current.push(Opcode::GetLocal { stack_offset: 0 });
-
-                    for _ in 0..info.scope_offset {
-                        current.push(Opcode::HeapRead { offset: 0 });
-                    }
-
-                    current.push(Opcode::HeapWrite {
-                        offset: info.var_offset,
-                    });
+                    self.convert_expression_to_ops(context, current, &offsets, value);
+                    store_variable(current, &offsets, *to);
+                }
+                TStmt::AssignMember {
+                    object,
+                    field,
+                    value,
+                    view,
+                    ..
+                } => {
+                    self.convert_expression_to_ops(context, current, &offsets, value);
+                    self.convert_expression_to_ops(context, current, &offsets, object);
+                    self.mark_source(context, current, *view);
+                    current.push(Opcode::HeapWrite { offset: field + 1 });
                 }
                 TStmt::Return { ret_val } => {
-                    self.convert_expression_to_ops(current, &offsets, ret_val);
+                    self.convert_expression_to_ops(context, current, &offsets, ret_val);
                     current.push(Opcode::SetLocal {
                         stack_offset: context.return_idx(),
                     });
@@ -318,7 +570,7 @@ impl Assembler {
                     if_true,
                     if_false,
                 } => {
-                    self.convert_expression_to_ops(current, &offsets, condition);
+                    self.convert_expression_to_ops(context, current, &offsets, condition);
 
                     let false_label = self.create_label(context.func_idx());
                     let end_label = self.create_label(context.func_idx());
@@ -326,7 +578,7 @@ impl Assembler {
                     current.push(Opcode::JumpNotIf(false_label));
                     self.assemble_block(
                         context,
-                        loop_label,
+                        loop_labels,
                         current,
                         offsets_(&offsets, false),
                         if_true,
@@ -335,15 +587,21 @@ impl Assembler {
                     self.attach_label(false_label, current.len() as u32);
                     self.assemble_block(
                         context,
-                        loop_label,
+                        loop_labels,
                         current,
                         offsets_(&offsets, false),
                         if_false,
                     );
                     self.attach_label(end_label, current.len() as u32);
                 }
+                TStmt::Trap { error } => {
+                    current.push(Opcode::Trap(*error));
+                }
                 TStmt::Break => {
-                    current.push(Opcode::Jump(loop_label.unwrap()));
+                    current.push(Opcode::Jump(loop_labels.unwrap().exit));
+                }
+                TStmt::Continue => {
+                    current.push(Opcode::Jump(loop_labels.unwrap().next));
                 }
                 TStmt::While {
                     condition,
@@ -355,11 +613,15 @@ impl Assembler {
                     let end = self.create_label(context.func_idx());
 
                     self.attach_label(begin, current.len() as u32);
-                    self.convert_expression_to_ops(current, &offsets, condition);
+                    self.convert_expression_to_ops(context, current, &offsets, condition);
                     current.push(Opcode::JumpNotIf(else_branch));
+                    let labels = LoopLabels {
+                        exit: end,
+                        next: begin,
+                    };
                     self.assemble_block(
                         context,
-                        Some(end),
+                        Some(labels),
                         current,
                         offsets_(&offsets, false),
                         block,
@@ -368,13 +630,65 @@ impl Assembler {
                     self.attach_label(else_branch, current.len() as u32);
                     self.assemble_block(
                         context,
-                        loop_label,
+                        loop_labels,
                         current,
                         offsets_(&offsets, false),
                         e_block,
                     );
                     self.attach_label(end, current.len() as u32);
                 }
+                TStmt::ForRange {
+                    var,
+                    start,
+                    stop,
+                    step,
+                    block,
+                    else_block,
+                    view,
+                } => {
+                    let begin = self.create_label(context.func_idx());
+                    let done = self.create_label(context.func_idx());
+                    let broken = self.create_label(context.func_idx());
+                    let end = self.create_label(context.func_idx());
+
+                    // The counter, stop and step stay on the stack for the
+                    // whole loop, so both ways out have to pop them
+                    self.convert_expression_to_ops(context, current, &offsets, start);
+                    self.convert_expression_to_ops(context, current, &offsets, stop);
+                    self.convert_expression_to_ops(context, current, &offsets, step);
+                    self.attach_label(begin, current.len() as u32);
+                    self.mark_source(context, current, *view);
+                    current.push(Opcode::ForRange(done));
+                    store_variable(current, &offsets, *var);
+
+                    let labels = LoopLabels {
+                        exit: broken,
+                        next: begin,
+                    };
+                    self.assemble_block(
+                        context,
+                        Some(labels),
+                        current,
+                        offsets_(&offsets, false),
+                        block,
+                    );
+                    current.push(Opcode::Jump(begin));
+
+                    self.attach_label(done, current.len() as u32);
+                    current.extend([Opcode::Pop, Opcode::Pop, Opcode::Pop]);
+                    self.assemble_block(
+                        context,
+                        loop_labels,
+                        current,
+                        offsets_(&offsets, false),
+                        else_block,
+                    );
+                    current.push(Opcode::Jump(end));
+
+                    self.attach_label(broken, current.len() as u32);
+                    current.extend([Opcode::Pop, Opcode::Pop, Opcode::Pop]);
+                    self.attach_label(end, current.len() as u32);
+                }
                 TStmt::Function {
                     uid,
                     argument_names,
@@ -386,12 +700,34 @@ impl Assembler {
                         self.assemble_function(*uid, argument_names, declarations, stmts, &offsets);
                     self.functions.insert(*uid, func_body);
                 }
+                TStmt::Class { methods, .. } => {
+                    for method in methods.iter() {
+                        if let TStmt::Function {
+                            uid,
+                            argument_names,
+                            declarations,
+                            stmts,
+                            ..
+                        } = method
+                        {
+                            let func_body = self.assemble_function(
+                                *uid,
+                                argument_names,
+                                declarations,
+                                stmts,
+                                &offsets,
+                            );
+                            self.functions.insert(*uid, func_body);
+                        }
+                    }
+                }
             }
         }
     }
 
     fn convert_expression_to_ops(
-        &self,
+        &mut self,
+        context: AsmContext,
         ops: &mut Vec<Opcode>,
         offsets: &OffsetTable,
         expr: &TExpr,
@@ -404,43 +740,125 @@ impl Assembler {
                 ops.push(Opcode::MakeBool(*value));
             }
             TExpr::Int(value) => {
-                ops.push(Opcode::MakeInt(*value as i64));
+                ops.push(Opcode::MakeInt(*value));
             }
             TExpr::Float(value) => {
                 ops.push(Opcode::MakeFloat(*value));
             }
+            TExpr::Str(value) => {
+                let id = self.add_string(value);
+                ops.push(Opcode::MakeStr(id));
+            }
             TExpr::Ident { id, .. } => {
-                let info = offsets.search(*id);
-                ops.push(Opcode::GetLocal { stack_offset: 0 });
-                for _ in 0..info.scope_offset {
-                    ops.push(Opcode::HeapRead { offset: 0 });
-                }
-
-                ops.push(Opcode::HeapRead {
-                    offset: info.var_offset,
+                load_variable(ops, offsets, *id);
+            }
+            TExpr::Binary {
+                op: BinOp::And,
+                left,
+                right,
+                ..
+            } => {
+                let end = self.create_label(context.func_idx());
+                self.convert_expression_to_ops(context, ops, offsets, left);
+                ops.push(Opcode::Dup);
+                ops.push(Opcode::JumpNotIf(end));
+                ops.push(Opcode::Pop);
+                self.convert_expression_to_ops(context, ops, offsets, right);
+                self.attach_label(end, ops.len() as u32);
+            }
+            TExpr::Binary {
+                op: BinOp::Or,
+                left,
+                right,
+                ..
+            } => {
+                let end = self.create_label(context.func_idx());
+                self.convert_expression_to_ops(context, ops, offsets, left);
+                ops.push(Opcode::Dup);
+                ops.push(Opcode::JumpIf(end));
+                ops.push(Opcode::Pop);
+                self.convert_expression_to_ops(context, ops, offsets, right);
+                self.attach_label(end, ops.len() as u32);
+            }
+            TExpr::Binary {
+                op,
+                left,
+                right,
+                view,
+                ..
+            } => {
+                self.convert_expression_to_ops(context, ops, offsets, left);
+                self.convert_expression_to_ops(context, ops, offsets, right);
+                self.mark_source(context, ops, *view);
+                let is_float = left.type_() == Type::Float;
+                ops.push(match op {
+                    BinOp::Add if left.type_() == Type::Str => Opcode::Concat,
+                    BinOp::Add if is_float => Opcode::AddFloat,
+                    BinOp::Add => Opcode::AddInt,
+                    BinOp::Sub if is_float => Opcode::SubFloat,
+                    BinOp::Sub => Opcode::SubInt,
+                    BinOp::Mul if is_float => Opcode::MulFloat,
+                    BinOp::Mul => Opcode::MulInt,
+                    BinOp::Div if is_float => Opcode::DivFloat,
+                    BinOp::Div => Opcode::DivInt,
+                    BinOp::FloorDiv if is_float => Opcode::FloorDivFloat,
+                    BinOp::FloorDiv => Opcode::FloorDivInt,
+                    BinOp::Mod if is_float => Opcode::ModFloat,
+                    BinOp::Mod => Opcode::ModInt,
+                    BinOp::Pow if is_float => Opcode::PowFloat,
+                    BinOp::Pow => Opcode::PowInt,
+                    BinOp::And | BinOp::Or => unreachable!(),
                 });
             }
-            TExpr::Minus { left, right, type_ } => {
-                self.convert_expression_to_ops(ops, offsets, left);
-                self.convert_expression_to_ops(ops, offsets, right);
-                if *type_ == Type::Float {
-                    ops.push(Opcode::SubFloat);
-                } else {
-                    ops.push(Opcode::SubInt);
+            TExpr::Unary {
+                op,
+                operand,
+                type_,
+                view,
+            } => {
+                self.convert_expression_to_ops(context, ops, offsets, operand);
+                self.mark_source(context, ops, *view);
+                match op {
+                    UnaryOp::Not => ops.push(Opcode::Not),
+                    UnaryOp::Neg if *type_ == Type::Float => ops.push(Opcode::NegFloat),
+                    UnaryOp::Neg => ops.push(Opcode::NegInt),
+                    UnaryOp::Pos => {}
                 }
             }
-            TExpr::Add { left, right, type_ } => {
-                self.convert_expression_to_ops(ops, offsets, left);
-                self.convert_expression_to_ops(ops, offsets, right);
-                if *type_ == Type::Float {
-                    ops.push(Opcode::AddFloat);
-                } else {
-                    ops.push(Opcode::AddInt);
+            TExpr::Compare { first, rest, view } => {
+                // Every operand in the chain is evaluated once; intermediate
+                // operands get duplicated so they can be used as the left side
+                // of the next comparison.
+                let fail = self.create_label(context.func_idx());
+                let end = self.create_label(context.func_idx());
+                self.convert_expression_to_ops(context, ops, offsets, first);
+                let (last, init) = rest.split_last().unwrap();
+                for comparison in init {
+                    self.convert_expression_to_ops(context, ops, offsets, &comparison.right);
+                    ops.push(Opcode::Dup);
+                    ops.push(Opcode::Rot);
+                    self.mark_source(context, ops, *view);
+                    ops.push(compare_op(comparison.op));
+                    ops.push(Opcode::Dup);
+                    ops.push(Opcode::JumpNotIf(fail));
+                    ops.push(Opcode::Pop);
                 }
+
+                self.convert_expression_to_ops(context, ops, offsets, &last.right);
+                self.mark_source(context, ops, *view);
+                ops.push(compare_op(last.op));
+                if !init.is_empty() {
+                    ops.push(Opcode::Jump(end));
+                    self.attach_label(fail, ops.len() as u32);
+                    ops.push(Opcode::Swap);
+                    ops.push(Opcode::Pop);
+                }
+                self.attach_label(end, ops.len() as u32);
             }
             TExpr::Call {
                 callee_uid,
                 arguments,
+                view,
                 ..
             } => {
                 let name = self.function_names[callee_uid];
@@ -451,20 +869,187 @@ impl Assembler {
                 }
 
                 for arg in arguments.iter().rev() {
-                    self.convert_expression_to_ops(ops, offsets, arg);
+                    self.convert_expression_to_ops(context, ops, offsets, arg);
                 }
 
+                self.mark_source(context, ops, *view);
                 ops.push(Opcode::Call(*callee_uid));
                 for _ in 0..arguments.len() {
                     ops.push(Opcode::Pop);
                 }
             }
-            TExpr::ECall { arguments } => {
+            // The class is read from the variable it's declared in, and the
+            // static link for `__init__` is read from the class
+            TExpr::New {
+                class,
+                name,
+                fields,
+                init,
+                arguments,
+                view,
+            } => {
+                ops.push(Opcode::HeapAlloc {
+                    header: ObjectHeader {
+                        type_index: self.class_type_index(*class),
+                        object_size: fields.len() as u32 + 1,
+                    },
+                });
+                ops.push(Opcode::Dup);
+                load_variable(ops, offsets, *name);
+                ops.push(Opcode::Swap);
+                ops.push(Opcode::HeapWrite { offset: 0 });
+                for (idx, field) in fields.iter().enumerate() {
+                    ops.push(Opcode::Dup);
+                    self.convert_expression_to_ops(context, ops, offsets, field);
+                    ops.push(Opcode::Swap);
+                    ops.push(Opcode::HeapWrite {
+                        offset: idx as u32 + 1,
+                    });
+                }
+
+                if let Some(init) = init {
+                    ops.push(Opcode::Dup);
+                    ops.push(Opcode::HeapRead { offset: 0 });
+                    ops.push(Opcode::HeapRead { offset: 0 });
+                    ops.push(Opcode::Pick(1));
+                    for arg in arguments.iter().rev() {
+                        self.convert_expression_to_ops(context, ops, offsets, arg);
+                    }
+
+                    self.mark_source(context, ops, *view);
+                    ops.push(Opcode::Call(*init));
+                    for _ in 0..=arguments.len() {
+                        ops.push(Opcode::Pop);
+                    }
+                    ops.push(Opcode::Pop);
+                }
+            }
+            TExpr::Member {
+                object,
+                field,
+                view,
+                ..
+            } => {
+                self.convert_expression_to_ops(context, ops, offsets, object);
+                self.mark_source(context, ops, *view);
+                ops.push(Opcode::HeapRead { offset: field + 1 });
+            }
+            // Methods take their object as their last argument, and are
+            // looked up in its class once the arguments have been evaluated
+            TExpr::MethodCall {
+                object,
+                method,
+                arguments,
+                view,
+                ..
+            } => {
+                self.convert_expression_to_ops(context, ops, offsets, object);
+                ops.push(Opcode::Dup);
+                self.mark_source(context, ops, *view);
+                ops.push(Opcode::HeapRead { offset: 0 });
+                ops.push(Opcode::HeapRead { offset: 0 });
+                ops.push(Opcode::Swap);
+                for arg in arguments.iter().rev() {
+                    self.convert_expression_to_ops(context, ops, offsets, arg);
+                }
+
+                ops.push(Opcode::Pick(arguments.len() as u32));
+                ops.push(Opcode::HeapRead { offset: 0 });
+                ops.push(Opcode::HeapRead { offset: method + 1 });
+                self.mark_source(context, ops, *view);
+                ops.push(Opcode::CallDyn);
+                for _ in 0..=arguments.len() {
+                    ops.push(Opcode::Pop);
+                }
+            }
+            // Classes implementing the interface get an itable for it. Values
+            // of another interface are still their objects, so the classes
+            // that have itables for that one get itables for this one too,
+            // and calls find them through the object's class like before.
+            TExpr::Upcast { value, interface } => {
+                self.convert_expression_to_ops(context, ops, offsets, value);
+                let interface_id = self.interface_id(interface);
+                match value.type_() {
+                    Type::Class(class) if self.itables.insert((class, interface_id)) => {
+                        let names = interface.iter().map(|m| m.name).collect();
+                        self.pending_itables.push((class, interface_id, names));
+                    }
+                    Type::Interface(from) => {
+                        let from_id = self.interface_id(from);
+                        self.interface_upcasts.insert((from_id, interface_id));
+                    }
+                    _ => {}
+                }
+            }
+            // Same as a method call, except the method is looked up in the
+            // itable for the object's class
+            TExpr::InterfaceCall {
+                object,
+                interface,
+                method,
+                arguments,
+                view,
+            } => {
+                self.convert_expression_to_ops(context, ops, offsets, object);
+                ops.push(Opcode::Dup);
+                self.mark_source(context, ops, *view);
+                ops.push(Opcode::HeapRead { offset: 0 });
+                ops.push(Opcode::HeapRead { offset: 0 });
+                ops.push(Opcode::Swap);
+                for arg in arguments.iter().rev() {
+                    self.convert_expression_to_ops(context, ops, offsets, arg);
+                }
+
+                ops.push(Opcode::Pick(arguments.len() as u32));
+                ops.push(Opcode::InterfaceMethod {
+                    interface: self.interface_id(interface),
+                    method: *method,
+                });
+                self.mark_source(context, ops, *view);
+                ops.push(Opcode::CallDyn);
+                for _ in 0..=arguments.len() {
+                    ops.push(Opcode::Pop);
+                }
+            }
+            TExpr::ECall { arguments, .. } => {
                 for arg in arguments.iter().rev() {
-                    self.convert_expression_to_ops(ops, offsets, arg);
+                    self.convert_expression_to_ops(context, ops, offsets, arg);
                 }
                 ops.push(Opcode::ECall);
             }
         }
     }
 }
+
+impl Default for Assembler {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+// Pushes the value of the variable `id`
+fn load_variable(ops: &mut Vec<Opcode>, offsets: &OffsetTable, id: u32) {
+    let info = offsets.search(id);
+    ops.push(Opcode::GetLocal { stack_offset: 0 });
+    for _ in 0..info.scope_offset {
+        ops.push(Opcode::HeapRead { offset: 0 });
+    }
+
+    ops.push(Opcode::HeapRead {
+        offset: info.var_offset,
+    });
+}
+
+// Pops the top of the stack into the variable `to`
+fn store_variable(ops: &mut Vec<Opcode>, offsets: &OffsetTable, to: u32) {
+    let info = offsets.search(to);
+    ops.push(Opcode::GetLocal { stack_offset: 0 });
+
+    for _ in 0..info.scope_offset {
+        ops.push(Opcode::HeapRead { offset: 0 });
+    }
+
+    ops.push(Opcode::HeapWrite {
+        offset: info.var_offset,
+    });
+}
@@ -3,16 +3,106 @@ use crate::syntax_tree::*;
 use crate::type_checker::*;
 use crate::util::*;
 use std::collections::HashMap;
+use std::rc::Rc;
 
 pub const PRINT_IDX: u32 = 1;
 pub const FLOAT_IDX: u32 = 2;
 pub const INT_IDX: u32 = 3;
 pub const BOOL_IDX: u32 = 4;
 pub const STR_IDX: u32 = 5;
+// Not a function; `for` loops lower calls to it
+pub const RANGE_IDX: u32 = 6;
+// Not a function either; the method that constructors call
+pub const INIT_IDX: u32 = 7;
 pub const FUNC_UID_BEGIN: u32 = 10;
 
-pub fn builtin_names<'a>() -> (Vec<&'a str>, HashMap<&'a str, u32>) {
-    let names = vec!["", "print", "float", "int", "bool", "str"];
+// Names of the builtins that are always there, by id
+pub const BUILTIN_NAMES: [&str; 8] = [
+    "", "print", "float", "int", "bool", "str", "range", "__init__",
+];
+
+// Ecalls below this are the runtime's own; the rest are host functions, in
+// the order they were registered
+pub const HOST_ECALL_BEGIN: u64 = 16;
+
+pub type HostCall = Rc<dyn Fn(&[Value]) -> Result<Value, &'static str>>;
+
+pub struct HostFunction {
+    pub name: String,
+    pub arguments: Vec<Type<'static>>,
+    pub return_type: Type<'static>,
+    pub call: HostCall,
+}
+
+// Rust functions that scripts can call like any other builtin. The same host
+// has to be used to compile a program and to run it.
+#[derive(Default)]
+pub struct Host {
+    pub functions: Vec<HostFunction>,
+}
+
+impl Host {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    // Arguments and the return value can be None or any primitive type. Each
+    // argument is either a value of its declared type or `Value::None`, since
+    // None can be passed for anything. Returning a value of the wrong type
+    // faults.
+    pub fn register(
+        &mut self,
+        name: &str,
+        arguments: &[Type<'static>],
+        return_type: Type<'static>,
+        call: impl Fn(&[Value]) -> Result<Value, &'static str> + 'static,
+    ) {
+        let valid = |type_: &Type| {
+            !matches!(
+                type_,
+                Type::Function { .. } | Type::Any | Type::Class(_) | Type::Interface(_)
+            )
+        };
+        assert!(
+            arguments.iter().all(valid) && valid(&return_type),
+            "host functions can only take and return None and primitive values"
+        );
+        let (names, _) = builtin_names(self);
+        assert!(!names.contains(&name), "`{}` is already defined", name);
+
+        self.functions.push(HostFunction {
+            name: String::from(name),
+            arguments: arguments.to_vec(),
+            return_type,
+            call: Rc::new(call),
+        });
+    }
+
+    pub fn first_free_uid(&self) -> u32 {
+        return FUNC_UID_BEGIN + self.functions.len() as u32;
+    }
+
+    fn uid(idx: usize) -> u32 {
+        return FUNC_UID_BEGIN + idx as u32;
+    }
+
+    fn ecall(idx: usize) -> u64 {
+        return HOST_ECALL_BEGIN + idx as u64;
+    }
+}
+
+// Host functions are named after the builtins, followed by the names of their
+// parameters, which can't be written in source and so can't collide with
+// anything.
+pub fn builtin_names(host: &Host) -> (Vec<&str>, HashMap<&str, u32>) {
+    let mut names = BUILTIN_NAMES.to_vec();
+    for function in &host.functions {
+        names.push(&function.name);
+    }
+    for param in PARAM_NAMES.iter().take(max_arity(host)) {
+        names.push(param);
+    }
+
     let mut names_map = HashMap::new();
     for (idx, name) in names.iter().enumerate() {
         names_map.insert(*name, idx as u32);
@@ -21,63 +111,135 @@ pub fn builtin_names<'a>() -> (Vec<&'a str>, HashMap<&'a str, u32>) {
     return (names, names_map);
 }
 
-pub fn builtin_symbols<'a, 'b>(buckets: &'b mut Buckets<'a>) -> HashMap<u32, SymbolInfo<'a>> {
+const PARAM_NAMES: [&str; 8] = [
+    "<arg0>", "<arg1>", "<arg2>", "<arg3>", "<arg4>", "<arg5>", "<arg6>", "<arg7>",
+];
+
+fn max_arity(host: &Host) -> usize {
+    let arity = host.functions.iter().map(|f| f.arguments.len()).max();
+    let arity = arity.unwrap_or(0);
+    assert!(
+        arity <= PARAM_NAMES.len(),
+        "host functions can't take more than 8 arguments"
+    );
+    return arity;
+}
+
+fn host_name(idx: usize) -> u32 {
+    return (BUILTIN_NAMES.len() + idx) as u32;
+}
+
+fn param_name(host: &Host, idx: usize) -> u32 {
+    return (BUILTIN_NAMES.len() + host.functions.len() + idx) as u32;
+}
+
+// `print` and the conversions take any value and pass it on to their ecall,
+// which checks what it is at runtime
+fn primitive_builtins() -> [(u32, u64, Type<'static>); 5] {
+    return [
+        (PRINT_IDX, PRINT_PRIMITIVE, Type::None),
+        (FLOAT_IDX, FLOAT_CAST, Type::Float),
+        (INT_IDX, INT_CAST, Type::Int),
+        (BOOL_IDX, BOOL_CAST, Type::Bool),
+        (STR_IDX, STR_CAST, Type::Str),
+    ];
+}
+
+pub fn builtin_symbols<'a>(buckets: &mut Buckets<'a>, host: &Host) -> HashMap<u32, SymbolInfo<'a>> {
     let mut map = HashMap::new();
-    let none_type = &*buckets.add(Type::None);
     let any_arg = &*buckets.add_array(vec![Type::Any]);
-    let print_type = &*buckets.add(Type::Function {
-        return_type: none_type,
-        arguments: any_arg,
-    });
-    map.insert(
-        PRINT_IDX,
-        SymbolInfo::Function {
-            uid: 1,
-            return_type: none_type,
-            arguments: any_arg,
-            view: newr(0, 0),
-        },
-    );
+    for &(name, _, return_type) in &primitive_builtins() {
+        map.insert(
+            name,
+            SymbolInfo::Function {
+                uid: name,
+                return_type: &*buckets.add(return_type),
+                arguments: any_arg,
+                view: newr(0, 0),
+            },
+        );
+    }
+
+    for (idx, function) in host.functions.iter().enumerate() {
+        map.insert(
+            host_name(idx),
+            SymbolInfo::Function {
+                uid: Host::uid(idx),
+                return_type: &*buckets.add(function.return_type),
+                arguments: &*buckets.add_array(function.arguments.clone()),
+                view: newr(0, 0),
+            },
+        );
+    }
     return map;
 }
 
-pub fn builtin_definitions<'a, 'b>(buckets: &'b mut Buckets<'a>) -> Vec<TStmt<'a>> {
+pub fn builtin_definitions<'a>(buckets: &mut Buckets<'a>, host: &Host) -> Vec<TStmt<'a>> {
     let mut defns = Vec::new();
 
-    let none_type = buckets.add(Type::None);
-    let int_type = buckets.add(Type::Int);
-    let any_arg = buckets.add_array(vec![Type::Any]);
-
-    let ecall_args = buckets.add_array(vec![
-        TExpr::Int(PRINT_PRIMITIVE as i64),
-        TExpr::Ident {
-            id: 1,
-            type_: Type::Any,
-        },
-    ]);
-
-    let ecall_expr = buckets.add(TExpr::ECall {
-        arguments: ecall_args,
-    });
-
-    let uids = buckets.add_array(vec![1]);
-
-    let stmts = buckets.add_array(vec![TStmt::Expr(ecall_expr)]);
-
-    defns.push(TStmt::Function {
-        uid: 1,
-        name: PRINT_IDX,
-        argument_names: uids,
-        declarations: buckets.add_array(vec![]),
-        stmts,
-    });
+    // Their parameters are named after themselves
+    for &(name, ecall, return_type) in &primitive_builtins() {
+        let ecall_args = buckets.add_array(vec![
+            TExpr::Int(ecall as i64),
+            TExpr::Ident {
+                id: name,
+                type_: Type::Any,
+            },
+        ]);
+        let ecall_expr = buckets.add(TExpr::ECall {
+            arguments: ecall_args,
+            type_: return_type,
+        });
+        let stmts = buckets.add_array(vec![TStmt::Return {
+            ret_val: ecall_expr,
+        }]);
+
+        let argument_names = buckets.add_array(vec![name]);
+        defns.push(TStmt::Function {
+            uid: name,
+            name,
+            argument_names,
+            declarations: buckets.add_array(vec![]),
+            stmts,
+        });
+    }
+
+    // Each host function passes its arguments straight on to its ecall
+    for (idx, function) in host.functions.iter().enumerate() {
+        let mut ecall_args = vec![TExpr::Int(Host::ecall(idx) as i64)];
+        let mut argument_names = Vec::new();
+        for (arg, &type_) in function.arguments.iter().enumerate() {
+            let id = param_name(host, arg);
+            ecall_args.push(TExpr::Ident { id, type_ });
+            argument_names.push(id);
+        }
+
+        let ecall_args = buckets.add_array(ecall_args);
+        let ecall_expr = buckets.add(TExpr::ECall {
+            arguments: ecall_args,
+            type_: function.return_type,
+        });
+        let stmts = buckets.add_array(vec![TStmt::Return {
+            ret_val: ecall_expr,
+        }]);
+
+        let argument_names = buckets.add_array(argument_names);
+        defns.push(TStmt::Function {
+            uid: Host::uid(idx),
+            name: host_name(idx),
+            argument_names,
+            declarations: buckets.add_array(vec![]),
+            stmts,
+        });
+    }
     return defns;
 }
 
-pub fn builtin_types<'a, 'b>(buckets: &'b mut Buckets<'a>) -> HashMap<u32, &'a Type<'a>> {
+pub fn builtin_types<'a>(buckets: &mut Buckets<'a>) -> HashMap<u32, &'a Type<'a>> {
     let mut map = HashMap::new();
     map.insert(FLOAT_IDX, &*buckets.add(Type::Float));
     map.insert(INT_IDX, &*buckets.add(Type::Int));
     map.insert(BOOL_IDX, &*buckets.add(Type::Bool));
+    map.insert(STR_IDX, &*buckets.add(Type::Str));
     return map;
 }
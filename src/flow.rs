@@ -0,0 +1,48 @@
+use crate::syntax_tree::*;
+
+// Whether running `stmts` never gets past their end, because every path
+// returns, breaks out, continues or loops forever. With `traps`, statements
+// that failed to check count too; the missing return check wants that, since
+// the error that caused them has already been reported, but the unreachable
+// code check doesn't, since the code after them would run if they were fixed.
+pub fn block_exits(stmts: &[TStmt], traps: bool) -> bool {
+    return stmts.iter().any(|stmt| exits(stmt, traps));
+}
+
+pub fn exits(stmt: &TStmt, traps: bool) -> bool {
+    return match stmt {
+        TStmt::Return { .. } | TStmt::Break | TStmt::Continue => true,
+        TStmt::Trap { .. } => traps,
+        TStmt::If {
+            if_true, if_false, ..
+        } => block_exits(if_true, traps) && block_exits(if_false, traps),
+        TStmt::While {
+            condition: TExpr::Bool(true),
+            block,
+            ..
+        } => !breaks(block),
+        // Loops that finish normally run their `else` block, and `break`
+        // skips it
+        TStmt::While {
+            block, else_block, ..
+        }
+        | TStmt::ForRange {
+            block, else_block, ..
+        } => !breaks(block) && block_exits(else_block, traps),
+        _ => false,
+    };
+}
+
+// Whether `stmts` can break out of the loop they're the body of. The bodies
+// of nested loops break out of those loops instead, but their `else` blocks
+// don't.
+fn breaks(stmts: &[TStmt]) -> bool {
+    return stmts.iter().any(|stmt| match stmt {
+        TStmt::Break => true,
+        TStmt::If {
+            if_true, if_false, ..
+        } => breaks(if_true) || breaks(if_false),
+        TStmt::While { else_block, .. } | TStmt::ForRange { else_block, .. } => breaks(else_block),
+        _ => false,
+    });
+}
@@ -0,0 +1,1034 @@
+use crate::builtins::{INIT_IDX, RANGE_IDX};
+use crate::syntax_tree::*;
+use crate::type_checker::SymbolInfo;
+use crate::util::*;
+use std::collections::HashMap;
+use std::mem;
+
+// Infers the missing parameter and return types of functions. Every
+// expression gets a type variable, and the constraints from the function
+// bodies and from every call site in the module are solved with union-find.
+// The type checker then checks the program against the inferred signatures.
+//
+// Objects aren't unified with the parameters they're passed to, so that a
+// parameter can take objects of more than one class. If a parameter is only
+// ever used to call methods, it gets an interface made up of those methods
+// instead of a class, and the type checker accepts objects of any class that
+// has them.
+
+#[derive(Debug, Clone)]
+pub struct Signature<'a> {
+    pub arguments: Vec<Type<'a>>,
+    pub return_type: Type<'a>,
+}
+
+#[derive(Clone, Copy)]
+struct TypeVar<'a> {
+    parent: usize,
+    type_: Option<Type<'a>>,
+    conflicted: bool,
+    linked: bool,
+}
+
+#[derive(Clone)]
+enum Binding {
+    Variable(usize),
+    Function {
+        arguments: Vec<usize>,
+        return_type: usize,
+    },
+    Class(u32),
+}
+
+// Methods are functions that take their object first
+#[derive(Clone)]
+struct ClassBinding {
+    fields: HashMap<u32, usize>,
+    methods: HashMap<u32, Binding>,
+}
+
+// What a parameter is used for in its function, and what's passed to it
+#[derive(Clone, Default)]
+struct Uses {
+    methods: Vec<MethodUse>,
+    // Names of the fields used, and their type variables
+    fields: Vec<(u32, usize)>,
+    classes: Vec<u32>,
+    // Parameters that this one gets passed on to, which need its objects to
+    // have their methods too
+    passed_to: Vec<usize>,
+}
+
+#[derive(Clone)]
+struct MethodUse {
+    name: u32,
+    arguments: Vec<usize>,
+    return_type: usize,
+}
+
+// An arithmetic operator or comparison whose operands are unified once the
+// call sites have been seen, since an int operand that's used with a float
+// gets widened instead. Comparisons and true division have no `result`.
+struct Operator {
+    left: usize,
+    right: usize,
+    result: Option<usize>,
+}
+
+struct Target {
+    key: u32,
+    arguments: Vec<(usize, Option<CRange>)>,
+    return_type: usize,
+    return_view: Option<CRange>,
+}
+
+pub struct Inferrer<'a, 'b> {
+    buckets: &'a mut Buckets<'b>,
+    types: &'a HashMap<u32, &'b Type<'b>>,
+    // Everything that was declared before the program being inferred
+    builtins: &'a HashMap<u32, SymbolInfo<'b>>,
+    vars: Vec<TypeVar<'b>>,
+    scopes: Vec<HashMap<u32, Binding>>,
+    return_types: Vec<usize>,
+    targets: Vec<Target>,
+    // By uid
+    classes: HashMap<u32, ClassBinding>,
+    // By the type variable of the parameter, not its root
+    uses: HashMap<usize, Uses>,
+    operators: Vec<Operator>,
+}
+
+impl<'a, 'b> Inferrer<'a, 'b> {
+    pub fn new(
+        buckets: &'a mut Buckets<'b>,
+        types: &'a HashMap<u32, &'b Type<'b>>,
+        builtins: &'a HashMap<u32, SymbolInfo<'b>>,
+    ) -> Self {
+        return Self {
+            buckets,
+            types,
+            builtins,
+            vars: Vec::new(),
+            scopes: vec![HashMap::new()],
+            return_types: Vec::new(),
+            targets: Vec::new(),
+            classes: HashMap::new(),
+            uses: HashMap::new(),
+            operators: Vec::new(),
+        };
+    }
+
+    // Returns the signatures of every function in the program, keyed by the
+    // start of the function's name.
+    pub fn infer_program(
+        mut self,
+        program: &[Stmt],
+    ) -> (HashMap<u32, Signature<'b>>, Vec<Error<'static>>) {
+        self.infer_block(program);
+        self.unify_operators();
+        self.unify_uses();
+
+        let mut signatures = HashMap::new();
+        let mut errors = Vec::new();
+        for target in mem::take(&mut self.targets) {
+            let mut arguments = Vec::new();
+            // Parameters that hold objects, by their roots
+            let mut objects = Vec::new();
+            for &(param_var, param) in &target.arguments {
+                let root = self.find(param_var);
+                let var = self.vars[root];
+                let view = match param {
+                    Some(view) => view,
+                    None => {
+                        arguments.push(var.type_.unwrap_or(Type::Any));
+                        continue;
+                    }
+                };
+
+                let object = self.object_type(param_var);
+                if let Some(Some(type_)) = object {
+                    arguments.push(type_);
+                    objects.push((root, type_));
+                } else if var.conflicted || object.is_some() {
+                    errors.push(Error {
+                        location: view,
+                        message:
+                            "conflicting types inferred for parameter, it needs a type annotation",
+                        code: Code::Inference,
+                    });
+                    arguments.push(Type::Any);
+                } else if let Some(type_) = var.type_ {
+                    arguments.push(type_);
+                } else {
+                    errors.push(Error {
+                        location: view,
+                        message: "couldn't infer parameter type, it needs a type annotation",
+                        code: Code::Inference,
+                    });
+                    arguments.push(Type::Any);
+                }
+            }
+
+            let root = self.find(target.return_type);
+            let var = self.vars[root];
+            let object = objects.iter().find(|&&(r, _)| r == root);
+            let return_type = match target.return_view {
+                None => var.type_.unwrap_or(Type::None),
+                // Parameters that are returned have the same type as the
+                // return value
+                Some(_) if object.is_some() => object.unwrap().1,
+                Some(view) if var.conflicted => {
+                    errors.push(Error {
+                        location: view,
+                        message:
+                            "conflicting types inferred for return value, it needs a type annotation",
+                        code: Code::Inference,
+                    });
+                    Type::Any
+                }
+                Some(view) => match var.type_ {
+                    Some(type_) => type_,
+                    // Nothing was ever returned
+                    None if !var.linked => Type::None,
+                    None => {
+                        errors.push(Error {
+                            location: view,
+                            message: "couldn't infer return type, it needs a type annotation",
+                            code: Code::Inference,
+                        });
+                        Type::Any
+                    }
+                },
+            };
+
+            signatures.insert(
+                target.key,
+                Signature {
+                    arguments,
+                    return_type,
+                },
+            );
+        }
+
+        return (signatures, errors);
+    }
+
+    fn new_var(&mut self, type_: Option<Type<'b>>) -> usize {
+        let idx = self.vars.len();
+        self.vars.push(TypeVar {
+            parent: idx,
+            type_,
+            conflicted: false,
+            linked: false,
+        });
+        return idx;
+    }
+
+    fn uses(&mut self, var: usize) -> &mut Uses {
+        return self.uses.entry(var).or_default();
+    }
+
+    fn find(&mut self, var: usize) -> usize {
+        let parent = self.vars[var].parent;
+        if parent == var {
+            return var;
+        }
+
+        let root = self.find(parent);
+        self.vars[var].parent = root;
+        return root;
+    }
+
+    // None and Any are weak; None can be assigned to anything, and Any accepts
+    // anything, so neither constrains the other side.
+    fn unify(&mut self, left: usize, right: usize) {
+        let left = self.find(left);
+        let right = self.find(right);
+        if left == right {
+            return;
+        }
+
+        // Interfaces come from earlier entries in the REPL, and accept
+        // objects of more than one class, so they're weak too
+        let ltype = self.vars[left].type_;
+        let rtype = self.vars[right].type_;
+        let weak = |t: Option<Type>| matches!(t, Some(Type::Any | Type::Interface(_)));
+        if weak(ltype) || weak(rtype) {
+            return;
+        }
+
+        let type_ = match (ltype, rtype) {
+            (None, t) | (t, None) => t,
+            (Some(Type::None), t) | (t, Some(Type::None)) => t,
+            (Some(l), Some(r)) if l == r => Some(l),
+            _ => {
+                // The type checker reports mismatches between known types;
+                // here we only need to remember that inference failed.
+                self.vars[left].conflicted = true;
+                self.vars[right].conflicted = true;
+                return;
+            }
+        };
+
+        let conflicted = self.vars[left].conflicted || self.vars[right].conflicted;
+        self.vars[right].parent = left;
+        self.vars[left] = TypeVar {
+            parent: left,
+            type_,
+            conflicted,
+            linked: true,
+        };
+    }
+
+    // An int and a float can be used together, since the int gets widened
+    fn mixed_numbers(&mut self, left: usize, right: usize) -> bool {
+        let left = self.find(left);
+        let right = self.find(right);
+        return matches!(
+            (self.vars[left].type_, self.vars[right].type_),
+            (Some(Type::Int), Some(Type::Float)) | (Some(Type::Float), Some(Type::Int))
+        );
+    }
+
+    // Operators with operands whose types are known go first, so that
+    // whether one is an int used with a float doesn't depend on the order the
+    // functions were written in. The rest are unified in order.
+    fn unify_operators(&mut self) {
+        let mut operators = mem::take(&mut self.operators);
+        while !operators.is_empty() {
+            let mut next = 0;
+            for (idx, operator) in operators.iter().enumerate() {
+                let (left, right) = (self.find(operator.left), self.find(operator.right));
+                if self.vars[left].type_.is_some() && self.vars[right].type_.is_some() {
+                    next = idx;
+                    break;
+                }
+            }
+
+            let operator = operators.remove(next);
+            if self.mixed_numbers(operator.left, operator.right) {
+                if let Some(result) = operator.result {
+                    let float = self.known(Type::Float);
+                    self.unify(result, float);
+                }
+                continue;
+            }
+
+            self.unify(operator.left, operator.right);
+            if let Some(result) = operator.result {
+                self.unify(result, operator.left);
+            }
+        }
+    }
+
+    // Objects are remembered by their class instead of being unified with
+    // the parameter, and parameters that get passed on are remembered too
+    fn pass(&mut self, formal: usize, arg: usize) {
+        let root = self.find(arg);
+        match self.vars[root].type_ {
+            Some(Type::Class(uid)) => {
+                let classes = &mut self.uses.entry(formal).or_default().classes;
+                if !classes.contains(&uid) {
+                    classes.push(uid);
+                }
+            }
+            _ => {
+                self.uses.entry(arg).or_default().passed_to.push(formal);
+                self.unify(formal, arg);
+            }
+        }
+    }
+
+    // Every parameter that objects passed to `var` end up in, `var` included
+    fn reachable(&self, var: usize) -> Vec<usize> {
+        let mut vars = vec![var];
+        let mut idx = 0;
+        while idx < vars.len() {
+            if let Some(uses) = self.uses.get(&vars[idx]) {
+                for &next in &uses.passed_to {
+                    if !vars.contains(&next) {
+                        vars.push(next);
+                    }
+                }
+            }
+            idx += 1;
+        }
+        return vars;
+    }
+
+    // The classes of the objects passed to `var`, including the one it was
+    // unified with
+    fn passed_classes(&mut self, var: usize) -> Vec<u32> {
+        let root = self.find(var);
+        let mut classes = match self.uses.get(&var) {
+            Some(uses) => uses.classes.clone(),
+            None => Vec::new(),
+        };
+        if let Some(Type::Class(uid)) = self.vars[root].type_ {
+            if !classes.contains(&uid) {
+                classes.push(uid);
+            }
+        }
+        return classes;
+    }
+
+    // Once every call has been seen, the objects passed to parameters are
+    // passed on to the parameters those get passed to, and the methods and
+    // fields used on each parameter are unified with those of the classes of
+    // its objects.
+    fn unify_uses(&mut self) {
+        let mut vars: Vec<usize> = self.uses.keys().copied().collect();
+        vars.sort();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &var in &vars {
+                let classes = self.passed_classes(var);
+                for next in self.uses[&var].passed_to.clone() {
+                    let next_classes = &mut self.uses.entry(next).or_default().classes;
+                    for &uid in &classes {
+                        if !next_classes.contains(&uid) {
+                            next_classes.push(uid);
+                            changed = true;
+                        }
+                    }
+                }
+            }
+        }
+
+        for var in vars {
+            let uses = self.uses[&var].clone();
+            for uid in self.passed_classes(var) {
+                let class = match self.class_binding(uid) {
+                    Some(class) => class,
+                    None => continue,
+                };
+                for method in &uses.methods {
+                    let (formals, return_type) = match class.methods.get(&method.name) {
+                        Some(Binding::Function {
+                            arguments,
+                            return_type,
+                        }) => (arguments, *return_type),
+                        _ => continue,
+                    };
+                    if formals.len() == method.arguments.len() + 1 {
+                        for (&formal, &arg) in formals.iter().skip(1).zip(&method.arguments) {
+                            self.constrain(arg, formal);
+                        }
+                    }
+                    self.constrain(method.return_type, return_type);
+                }
+                for &(name, field) in &uses.fields {
+                    if let Some(&class_field) = class.fields.get(&name) {
+                        self.constrain(field, class_field);
+                    }
+                }
+            }
+        }
+    }
+
+    // Gives `var` the type of `by` without unifying them, since `by` belongs
+    // to a class that other parameters get objects of too. If the types don't
+    // match, only `var` is marked as conflicted.
+    fn constrain(&mut self, var: usize, by: usize) {
+        let by_root = self.find(by);
+        let type_ = match self.vars[by_root].type_ {
+            Some(type_) => type_,
+            None => return,
+        };
+
+        let root = self.find(var);
+        match self.vars[root].type_ {
+            Some(Type::None | Type::Any | Type::Interface(_)) | None => {}
+            Some(current) if current != type_ && type_ != Type::None => {
+                self.vars[root].conflicted = true;
+                return;
+            }
+            Some(_) => {}
+        }
+        let known = self.known(type_);
+        self.unify(var, known);
+    }
+
+    // The type of a parameter that's used on objects or has them passed to
+    // it: an interface made up of the methods called on it if that's all it's
+    // used for, or else the class of its objects. It's None inside if that
+    // can't be worked out.
+    fn object_type(&mut self, var: usize) -> Option<Option<Type<'b>>> {
+        if !self.uses.contains_key(&var) {
+            return None;
+        }
+
+        let classes = self.passed_classes(var);
+        let root = self.find(var);
+        match self.vars[root].type_ {
+            _ if self.vars[root].conflicted => return None,
+            None | Some(Type::None | Type::Class(_)) => {}
+            Some(_) if classes.is_empty() => return None,
+            Some(_) => return Some(None),
+        }
+
+        let mut methods = Vec::new();
+        let mut fields = false;
+        for reachable in self.reachable(var) {
+            if let Some(uses) = self.uses.get(&reachable) {
+                methods.extend(uses.methods.iter().cloned());
+                fields |= !uses.fields.is_empty();
+            }
+        }
+        if !methods.is_empty() && !fields {
+            return Some(self.interface(methods));
+        }
+
+        return match classes[..] {
+            [] => None,
+            [uid] => Some(Some(Type::Class(uid))),
+            _ => Some(None),
+        };
+    }
+
+    // None if calls to the same method don't agree on its type
+    fn interface(&mut self, mut methods: Vec<MethodUse>) -> Option<Type<'b>> {
+        methods.sort_by_key(|m| m.name);
+        let mut interface: Vec<InterfaceMethod> = Vec::new();
+        for method in methods {
+            let mut arguments = Vec::new();
+            for &arg in &method.arguments {
+                arguments.push(self.resolved(arg)?);
+            }
+            let return_type = self.resolved(method.return_type)?;
+
+            if let Some(prev) = interface.last() {
+                if prev.name == method.name {
+                    if *prev.arguments != *arguments || *prev.return_type != return_type {
+                        return None;
+                    }
+                    continue;
+                }
+            }
+            interface.push(InterfaceMethod {
+                name: method.name,
+                arguments: self.buckets.add_array(arguments),
+                return_type: self.buckets.add(return_type),
+            });
+        }
+        return Some(Type::Interface(self.buckets.add_array(interface)));
+    }
+
+    // What an interface's method takes or returns; anything if nothing more
+    // is known
+    fn resolved(&mut self, var: usize) -> Option<Type<'b>> {
+        let root = self.find(var);
+        let var = self.vars[root];
+        if var.conflicted {
+            return None;
+        }
+        return Some(var.type_.unwrap_or(Type::Any));
+    }
+
+    fn known(&mut self, type_: Type<'b>) -> usize {
+        return self.new_var(Some(type_));
+    }
+
+    fn annotation(&self, type_name: Option<u32>) -> Option<Type<'b>> {
+        return type_name.and_then(|name| self.types.get(&name).map(|t| **t));
+    }
+
+    fn lookup(&self, name: u32) -> Option<Binding> {
+        for scope in self.scopes.iter().rev() {
+            if let Some(binding) = scope.get(&name) {
+                return Some(binding.clone());
+            }
+        }
+        return None;
+    }
+
+    fn declare(&mut self, name: u32, binding: Binding) {
+        self.scopes.last_mut().unwrap().insert(name, binding);
+    }
+
+    fn infer_block(&mut self, stmts: &[Stmt]) {
+        // Functions can be called before they're defined, same as in the type
+        // checker
+        for stmt in stmts {
+            match stmt {
+                Stmt::Function { name, .. } => {
+                    let binding = self.function_binding(stmt, None);
+                    self.declare(*name, binding);
+                }
+                Stmt::Class {
+                    name,
+                    fields,
+                    methods,
+                    ..
+                } => {
+                    // Classes that the type checker didn't accept aren't
+                    // types
+                    let uid = match self.annotation(Some(*name)) {
+                        Some(Type::Class(uid)) => uid,
+                        _ => continue,
+                    };
+
+                    let mut class = ClassBinding {
+                        fields: HashMap::new(),
+                        methods: HashMap::new(),
+                    };
+                    for field in fields.iter() {
+                        let type_ = self.annotation(Some(field.type_name));
+                        class.fields.insert(field.name, self.new_var(type_));
+                    }
+                    for method in methods.iter() {
+                        if let Stmt::Function { name, .. } = method {
+                            let binding = self.function_binding(method, Some(Type::Class(uid)));
+                            class.methods.insert(*name, binding);
+                        }
+                    }
+
+                    self.classes.insert(uid, class);
+                    self.declare(*name, Binding::Class(uid));
+                }
+                _ => {}
+            }
+        }
+
+        for stmt in stmts {
+            self.infer_stmt(stmt);
+        }
+    }
+
+    // Makes `function` a target. The first parameter of a method is known to
+    // be its object.
+    fn function_binding(&mut self, function: &Stmt, object: Option<Type<'b>>) -> Binding {
+        let (name_view, arguments, return_type, stmts) = match function {
+            Stmt::Function {
+                name_view,
+                arguments,
+                return_type,
+                stmts,
+                ..
+            } => (name_view, arguments, return_type, stmts),
+            _ => panic!("expected a function"),
+        };
+
+        let mut arg_vars = Vec::new();
+        let mut target_args = Vec::new();
+        for (idx, arg) in arguments.iter().enumerate() {
+            let type_ = match object {
+                Some(object) if idx == 0 => Some(object),
+                _ => self.annotation(arg.type_name),
+            };
+            let var = self.new_var(type_);
+            arg_vars.push(var);
+            match type_ {
+                Some(_) => target_args.push((var, None)),
+                None => target_args.push((var, Some(arg.view))),
+            }
+        }
+
+        // Without a return statement the function can only return None
+        let type_ = match self.annotation(*return_type) {
+            None if !has_return(stmts) => Some(Type::None),
+            type_ => type_,
+        };
+        let return_var = self.new_var(type_);
+
+        self.targets.push(Target {
+            key: name_view.start,
+            arguments: target_args,
+            return_type: return_var,
+            return_view: match return_type {
+                Some(_) => None,
+                None => Some(*name_view),
+            },
+        });
+
+        return Binding::Function {
+            arguments: arg_vars,
+            return_type: return_var,
+        };
+    }
+
+    fn infer_function(&mut self, arguments: &[FuncParam], stmts: &[Stmt], binding: Binding) {
+        let (arg_vars, return_type) = match binding {
+            Binding::Function {
+                arguments,
+                return_type,
+            } => (arguments, return_type),
+            _ => return,
+        };
+
+        let mut scope = HashMap::new();
+        for (arg, var) in arguments.iter().zip(arg_vars) {
+            scope.insert(arg.name, Binding::Variable(var));
+        }
+
+        self.scopes.push(scope);
+        self.return_types.push(return_type);
+        self.infer_block(stmts);
+        self.return_types.pop();
+        self.scopes.pop();
+    }
+
+    // The class of the object `var` holds, if that's known by now
+    fn class_of(&mut self, var: usize) -> Option<ClassBinding> {
+        return match self.passed_classes(var)[..] {
+            [uid] => self.class_binding(uid),
+            _ => None,
+        };
+    }
+
+    // Classes declared before the program being inferred are bound to their
+    // checked types the first time they're needed
+    fn class_binding(&mut self, uid: u32) -> Option<ClassBinding> {
+        if let Some(class) = self.classes.get(&uid) {
+            return Some(class.clone());
+        }
+
+        let info = self.builtins.values().find_map(|symbol| match symbol {
+            SymbolInfo::Class { info, .. } if info.uid == uid => Some(*info),
+            _ => None,
+        })?;
+        let mut class = ClassBinding {
+            fields: HashMap::new(),
+            methods: HashMap::new(),
+        };
+        for field in info.fields {
+            class.fields.insert(field.name, self.known(field.type_));
+        }
+        for method in info.methods {
+            let mut arguments = vec![self.known(Type::Class(uid))];
+            for &argument in method.arguments {
+                arguments.push(self.known(argument));
+            }
+            let binding = Binding::Function {
+                arguments,
+                return_type: self.known(*method.return_type),
+            };
+            class.methods.insert(method.name, binding);
+        }
+
+        self.classes.insert(uid, class.clone());
+        return Some(class);
+    }
+
+    // Unifies the arguments of a method call with the method's parameters,
+    // skipping its object, and returns its return type
+    fn unify_method_arguments(
+        &mut self,
+        method: Option<Binding>,
+        args: Vec<usize>,
+    ) -> Option<usize> {
+        let (formals, return_type) = match method {
+            Some(Binding::Function {
+                arguments,
+                return_type,
+            }) => (arguments, return_type),
+            _ => return None,
+        };
+
+        if formals.len() == args.len() + 1 {
+            for (formal, arg) in formals.into_iter().skip(1).zip(args) {
+                self.pass(formal, arg);
+            }
+        }
+        return Some(return_type);
+    }
+
+    fn infer_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Pass(_) | Stmt::Break(_) | Stmt::Continue(_) => {}
+            Stmt::Expr(expr) => {
+                self.infer_expr(expr);
+            }
+            Stmt::Declare {
+                name,
+                type_name,
+                value,
+                ..
+            } => {
+                let type_ = self.annotation(Some(*type_name));
+                let var = self.new_var(type_);
+                let value = self.infer_expr(value);
+                self.unify(var, value);
+                self.declare(*name, Binding::Variable(var));
+            }
+            Stmt::Assign { to, value, .. } => {
+                let value = self.infer_expr(value);
+                match self.lookup(*to) {
+                    Some(Binding::Variable(var)) => self.unify(var, value),
+                    Some(Binding::Function { .. } | Binding::Class(_)) => {}
+                    None => self.declare(*to, Binding::Variable(value)),
+                }
+            }
+            Stmt::AssignMember {
+                to,
+                to_member,
+                value,
+                ..
+            } => {
+                let object = self.infer_expr(to);
+                let value = self.infer_expr(value);
+                let class = self.class_of(object);
+                if let Some(&field) = class.as_ref().and_then(|c| c.fields.get(to_member)) {
+                    self.unify(field, value);
+                }
+                self.uses(object).fields.push((*to_member, value));
+            }
+            Stmt::Return { ret_val } => {
+                let value = self.infer_expr(ret_val);
+                if let Some(&return_type) = self.return_types.last() {
+                    self.unify(return_type, value);
+                }
+            }
+            Stmt::Function {
+                name,
+                arguments,
+                stmts,
+                ..
+            } => {
+                if let Some(binding) = self.scopes.last().unwrap().get(name).cloned() {
+                    self.infer_function(arguments, stmts, binding);
+                }
+            }
+            Stmt::Class { name, methods, .. } => {
+                let class = match self.scopes.last().unwrap().get(name) {
+                    Some(Binding::Class(uid)) => self.classes[uid].clone(),
+                    _ => return,
+                };
+
+                for method in methods.iter() {
+                    if let Stmt::Function {
+                        name,
+                        arguments,
+                        stmts,
+                        ..
+                    } = method
+                    {
+                        self.infer_function(arguments, stmts, class.methods[name].clone());
+                    }
+                }
+            }
+            Stmt::If {
+                conditioned_blocks,
+                else_branch,
+            } => {
+                for branch in conditioned_blocks.iter() {
+                    self.infer_expr(&branch.condition);
+                    self.infer_block(branch.block);
+                }
+                self.infer_block(else_branch);
+            }
+            Stmt::While {
+                condition,
+                block,
+                else_branch,
+            } => {
+                self.infer_expr(condition);
+                self.infer_block(block);
+                self.infer_block(else_branch);
+            }
+            Stmt::For {
+                target,
+                iterable,
+                block,
+                else_branch,
+                ..
+            } => {
+                // Only ranges can be looped over, so everything is an int
+                let int = self.known(Type::Int);
+                match iterable {
+                    Expr::Call {
+                        callee: RANGE_IDX,
+                        arguments,
+                        ..
+                    } if self.lookup(RANGE_IDX).is_none() => {
+                        for argument in arguments.iter() {
+                            let argument = self.infer_expr(argument);
+                            self.unify(int, argument);
+                        }
+                    }
+                    _ => {
+                        self.infer_expr(iterable);
+                    }
+                }
+
+                match self.lookup(*target) {
+                    Some(Binding::Variable(var)) => self.unify(var, int),
+                    Some(Binding::Function { .. } | Binding::Class(_)) => {}
+                    None => self.declare(*target, Binding::Variable(int)),
+                }
+                self.infer_block(block);
+                self.infer_block(else_branch);
+            }
+        }
+    }
+
+    fn infer_expr(&mut self, expr: &Expr) -> usize {
+        match expr {
+            Expr::Int { .. } => return self.known(Type::Int),
+            Expr::Float { .. } => return self.known(Type::Float),
+            Expr::StringLiteral { .. } => return self.known(Type::Str),
+            Expr::None(_) => return self.known(Type::None),
+            Expr::True(_) | Expr::False(_) => return self.known(Type::Bool),
+            Expr::Ident { id, .. } => match self.lookup(*id) {
+                Some(Binding::Variable(var)) => return var,
+                Some(Binding::Function { .. } | Binding::Class(_)) => return self.new_var(None),
+                None => match self.builtins.get(id) {
+                    Some(SymbolInfo::Variable { type_, .. }) => return self.known(**type_),
+                    _ => return self.new_var(None),
+                },
+            },
+            Expr::Binary {
+                op, left, right, ..
+            } => {
+                let left = self.infer_expr(left);
+                let right = self.infer_expr(right);
+                let (type_, result) = match op {
+                    BinOp::And | BinOp::Or => {
+                        self.unify(left, right);
+                        return left;
+                    }
+                    BinOp::Div => (self.known(Type::Float), None),
+                    _ => {
+                        let result = self.new_var(None);
+                        (result, Some(result))
+                    }
+                };
+                self.operators.push(Operator {
+                    left,
+                    right,
+                    result,
+                });
+                return type_;
+            }
+            Expr::Unary { op, operand, .. } => {
+                let operand = self.infer_expr(operand);
+                match op {
+                    UnaryOp::Not => return self.known(Type::Bool),
+                    UnaryOp::Neg | UnaryOp::Pos => return operand,
+                }
+            }
+            Expr::Compare { first, rest, .. } => {
+                let mut prev = self.infer_expr(first);
+                for comparison in rest.iter() {
+                    let right = self.infer_expr(&comparison.right);
+                    self.operators.push(Operator {
+                        left: prev,
+                        right,
+                        result: None,
+                    });
+                    prev = right;
+                }
+                return self.known(Type::Bool);
+            }
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                let args: Vec<usize> = arguments.iter().map(|arg| self.infer_expr(arg)).collect();
+                match self.lookup(*callee) {
+                    Some(Binding::Function {
+                        arguments: formals,
+                        return_type,
+                    }) => {
+                        if formals.len() == args.len() {
+                            for (formal, arg) in formals.into_iter().zip(args) {
+                                self.pass(formal, arg);
+                            }
+                        }
+                        return return_type;
+                    }
+                    Some(Binding::Class(uid)) => {
+                        let init = self.classes[&uid].methods.get(&INIT_IDX).cloned();
+                        self.unify_method_arguments(init, args);
+                        return self.known(Type::Class(uid));
+                    }
+                    Some(Binding::Variable(_)) => return self.new_var(None),
+                    None => {}
+                }
+
+                match self.builtins.get(callee) {
+                    Some(SymbolInfo::Function {
+                        return_type,
+                        arguments: formals,
+                        ..
+                    }) => {
+                        if formals.len() == args.len() {
+                            for (formal, arg) in formals.iter().zip(args) {
+                                let formal = self.known(*formal);
+                                self.unify(formal, arg);
+                            }
+                        }
+                        return self.known(**return_type);
+                    }
+                    Some(SymbolInfo::Class { info, .. }) => {
+                        return self.known(Type::Class(info.uid))
+                    }
+                    _ => return self.new_var(None),
+                }
+            }
+            Expr::DotAccess {
+                parent, member_id, ..
+            } => {
+                let object = self.infer_expr(parent);
+                let class = self.class_of(object);
+                let field = match class.as_ref().and_then(|c| c.fields.get(member_id)) {
+                    Some(&field) => field,
+                    None => self.new_var(None),
+                };
+                self.uses(object).fields.push((*member_id, field));
+                return field;
+            }
+            Expr::MethodCall {
+                parent,
+                method,
+                arguments,
+                ..
+            } => {
+                let object = self.infer_expr(parent);
+                let args: Vec<usize> = arguments.iter().map(|arg| self.infer_expr(arg)).collect();
+                let class = self.class_of(object);
+                let binding = class.and_then(|mut c| c.methods.remove(method));
+                let return_type = match self.unify_method_arguments(binding, args.clone()) {
+                    Some(return_type) => return_type,
+                    None => self.new_var(None),
+                };
+                self.uses(object).methods.push(MethodUse {
+                    name: *method,
+                    arguments: args,
+                    return_type,
+                });
+                return return_type;
+            }
+            Expr::Tup { values, .. } => {
+                for value in values.iter() {
+                    self.infer_expr(value);
+                }
+                return self.new_var(None);
+            }
+        }
+    }
+}
+
+// Whether `stmts` return from the function they're in; nested functions
+// don't count
+fn has_return(stmts: &[Stmt]) -> bool {
+    return stmts.iter().any(|stmt| match stmt {
+        Stmt::Return { .. } => true,
+        Stmt::If {
+            conditioned_blocks,
+            else_branch,
+        } => {
+            conditioned_blocks
+                .iter()
+                .any(|branch| has_return(branch.block))
+                || has_return(else_branch)
+        }
+        Stmt::While {
+            block, else_branch, ..
+        }
+        | Stmt::For {
+            block, else_branch, ..
+        } => has_return(block) || has_return(else_branch),
+        _ => false,
+    });
+}
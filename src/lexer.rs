@@ -1,5 +1,5 @@
-use crate::builtins::builtin_names;
-use crate::util::{newr, CRange};
+use crate::builtins::BUILTIN_NAMES;
+use crate::util::{newr, CRange, Code, Error, Silenced};
 use std::collections::HashMap;
 use std::num::NonZeroU32;
 use std::str::from_utf8_unchecked;
@@ -15,14 +15,36 @@ pub enum Token {
     Else(u32),
     Elif(u32),
     While(u32),
+    For(u32),
+    Class(u32),
+    In(u32),
     Break(u32),
+    Continue(u32),
+    And(u32),
+    Or(u32),
+    Not(u32),
     Ident {
         id: u32,
         view: CRange,
     },
     LParen(u32),
     RParen(u32),
+    LBracket(u32),
+    RBracket(u32),
+    LBrace(u32),
+    RBrace(u32),
     Plus(u32),
+    Star(u32),
+    DoubleStar(u32),
+    Slash(u32),
+    DoubleSlash(u32),
+    Percent(u32),
+    EqualEqual(u32),
+    NotEqual(u32),
+    Less(u32),
+    LessEqual(u32),
+    Greater(u32),
+    GreaterEqual(u32),
     Comma(u32),
     Newline(u32),
     Colon(u32),
@@ -60,10 +82,69 @@ pub enum Token {
 }
 
 impl Token {
+    pub fn name(self) -> &'static str {
+        use Token::*;
+        return match self {
+            Pass(_) => "pass",
+            Return(_) => "return",
+            None(_) => "None",
+            True(_) => "True",
+            False(_) => "False",
+            If(_) => "if",
+            Else(_) => "else",
+            Elif(_) => "elif",
+            While(_) => "while",
+            For(_) => "for",
+            Class(_) => "class",
+            In(_) => "in",
+            Break(_) => "break",
+            Continue(_) => "continue",
+            And(_) => "and",
+            Or(_) => "or",
+            Not(_) => "not",
+            Ident { .. } => "identifier",
+            LParen(_) => "(",
+            RParen(_) => ")",
+            LBracket(_) => "[",
+            RBracket(_) => "]",
+            LBrace(_) => "{",
+            RBrace(_) => "}",
+            Plus(_) => "+",
+            Star(_) => "*",
+            DoubleStar(_) => "**",
+            Slash(_) => "/",
+            DoubleSlash(_) => "//",
+            Percent(_) => "%",
+            EqualEqual(_) => "==",
+            NotEqual(_) => "!=",
+            Less(_) => "<",
+            LessEqual(_) => "<=",
+            Greater(_) => ">",
+            GreaterEqual(_) => ">=",
+            Comma(_) => ",",
+            Newline(_) => "newline",
+            Colon(_) => ":",
+            Dash(_) => "-",
+            Dot(_) => ".",
+            Equal(_) => "=",
+            Def(_) => "def",
+            Arrow(_) => "->",
+            Indent { .. } => "indent",
+            Dedent(_) => "dedent",
+            UnknownDedent(_) => "unknown dedent",
+            Unknown { .. } => "unknown",
+            End(_) => "end",
+            Integer { .. } => "integer",
+            FloatingPoint { .. } => "float",
+            String { .. } => "string",
+            IncompleteString(_) => "incomplete string",
+        };
+    }
+
     pub fn view(self) -> CRange {
         use Token::*;
         return match self {
-            Pass(x) => newr(x, x + 1),
+            Pass(x) => newr(x, x + 4),
             Return(x) => newr(x, x + 6),
             None(x) => newr(x, x + 4),
             True(x) => newr(x, x + 4),
@@ -72,11 +153,33 @@ impl Token {
             Else(x) => newr(x, x + 4),
             Elif(x) => newr(x, x + 4),
             While(x) => newr(x, x + 5),
+            For(x) => newr(x, x + 3),
+            Class(x) => newr(x, x + 5),
+            In(x) => newr(x, x + 2),
             Break(x) => newr(x, x + 5),
-            Ident { id, view } => view,
+            Continue(x) => newr(x, x + 8),
+            And(x) => newr(x, x + 3),
+            Or(x) => newr(x, x + 2),
+            Not(x) => newr(x, x + 3),
+            Ident { view, .. } => view,
             LParen(x) => newr(x, x + 1),
             RParen(x) => newr(x, x + 1),
+            LBracket(x) => newr(x, x + 1),
+            RBracket(x) => newr(x, x + 1),
+            LBrace(x) => newr(x, x + 1),
+            RBrace(x) => newr(x, x + 1),
             Plus(x) => newr(x, x + 1),
+            Star(x) => newr(x, x + 1),
+            DoubleStar(x) => newr(x, x + 2),
+            Slash(x) => newr(x, x + 1),
+            DoubleSlash(x) => newr(x, x + 2),
+            Percent(x) => newr(x, x + 1),
+            EqualEqual(x) => newr(x, x + 2),
+            NotEqual(x) => newr(x, x + 2),
+            Less(x) => newr(x, x + 1),
+            LessEqual(x) => newr(x, x + 2),
+            Greater(x) => newr(x, x + 1),
+            GreaterEqual(x) => newr(x, x + 2),
             Dot(x) => newr(x, x + 1),
             Def(x) => newr(x, x + 3),
             Comma(x) => newr(x, x + 1),
@@ -86,8 +189,8 @@ impl Token {
             Arrow(x) => newr(x, x + 2),
             Newline(x) => newr(x, x + 1),
             Indent { begin, end } => newr(begin, end),
-            Integer { value, begin, end } => newr(begin, end.get()),
-            FloatingPoint { value, begin, end } => newr(begin, end.get()),
+            Integer { begin, end, .. } => newr(begin, end.get()),
+            FloatingPoint { begin, end, .. } => newr(begin, end.get()),
             String { view, .. } => view,
             IncompleteString(x) => x,
             Dedent(x) => newr(x, x),
@@ -111,29 +214,53 @@ pub struct Lexer<'a> {
     pub id_list: Vec<&'a str>,
     pub id_map: HashMap<&'a str, u32>,
     pub string_map: HashMap<&'a str, u32>,
+    // Problems that still leave a usable token, so lexing carries on
+    pub errors: Vec<Error<'static>>,
     indent_stack: Vec<u16>,
     index: u32,
     indent_level: u16,
-    paren_count: u8,
+    // Newlines and indentation don't count inside brackets
+    bracket_depth: u32,
+    // Whichever of space and tab was used to indent first
+    indent_char: Option<u8>,
     state: LexerState,
+    silenced: Silenced,
+    // Where each code was disabled by a pragma that hasn't been matched by an
+    // enable yet
+    disabled_since: HashMap<Code, u32>,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(data: &'a str) -> Self {
-        let (id_list, id_map) = builtin_names();
+        return Self::with_names(data, BUILTIN_NAMES.to_vec());
+    }
+
+    // Starts from an existing list of identifiers, so that the same names
+    // keep the same ids across separately lexed sources
+    pub fn with_names(data: &'a str, id_list: Vec<&'a str>) -> Self {
+        let mut id_map = HashMap::new();
+        for (idx, name) in id_list.iter().enumerate() {
+            id_map.insert(*name, idx as u32);
+        }
+
         return Lexer {
             data: data.as_bytes(),
             id_list,
             id_map,
             string_map: HashMap::new(),
+            errors: Vec::new(),
             indent_stack: vec![0],
             index: 0,
             indent_level: 0,
-            paren_count: 0,
+            bracket_depth: 0,
+            indent_char: None,
             state: LexerState::Indentation,
+            silenced: Silenced::default(),
+            disabled_since: HashMap::new(),
         };
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> Token {
         return match self.state {
             LexerState::Dedent => self.next_dedent(),
@@ -150,6 +277,16 @@ impl<'a> Lexer<'a> {
         };
     }
 
+    // The regions silenced by the pragmas seen so far. Ones that were never
+    // enabled again run to the end of the source.
+    pub fn take_silenced(&mut self) -> Silenced {
+        let end = self.data.len() as u32;
+        for (code, begin) in self.disabled_since.drain() {
+            self.silenced.add(code, newr(begin, end));
+        }
+        return std::mem::take(&mut self.silenced);
+    }
+
     pub fn substr<'b>(&'b self, start: u32, end: u32) -> &'a str {
         return unsafe { from_utf8_unchecked(&self.data[(start as usize)..(end as usize)]) };
     }
@@ -164,30 +301,63 @@ impl<'a> Lexer<'a> {
         return self.index == self.data.len() as u32;
     }
 
+    // Consumes the current character if it's `c`
+    fn eat(&mut self, c: u8) -> bool {
+        if !self.at_end() && self.cur() == c {
+            self.index += 1;
+            return true;
+        }
+        return false;
+    }
+
     fn next_indent(&mut self) -> Token {
         let mut indent_level: u16 = 0;
         let mut begin = self.index;
+        let mut line_begin = self.index;
+        let (mut spaces, mut tabs) = (false, false);
         while !self.at_end() {
             match self.cur() {
                 b'\n' => {
                     indent_level = 0;
                     begin = self.index;
                     self.index += 1;
+                    line_begin = self.index;
+                    spaces = false;
+                    tabs = false;
                 }
                 b' ' => {
                     indent_level += 1;
-                    self.index += 2;
+                    self.index += 1;
+                    spaces = true;
                 }
                 b'\t' => {
                     indent_level += 8 - indent_level % 8;
                     self.index += 1;
+                    tabs = true;
                 }
+                b'#' => self.skip_comment(),
                 _ => {
                     break;
                 }
             }
         }
 
+        // Blank lines and comments can be indented however they like
+        if !self.at_end() && (spaces || tabs) {
+            let used = if spaces { b' ' } else { b'\t' };
+            let consistent = !(spaces && tabs) && *self.indent_char.get_or_insert(used) == used;
+            if !consistent {
+                self.errors.push(Error {
+                    location: newr(line_begin, self.index),
+                    message: "inconsistent use of tabs and spaces in indentation",
+                    code: Code::Syntax,
+                });
+                // There's no telling what was meant, so carry on at the
+                // current level instead of piling on more errors
+                indent_level = *self.indent_stack.last().unwrap();
+            }
+        }
+
         let prev_indent = *self.indent_stack.last().unwrap();
         if indent_level < prev_indent {
             self.state = LexerState::Dedent;
@@ -209,6 +379,169 @@ impl<'a> Lexer<'a> {
         }
     }
 
+    // Skips to the end of the line, taking note of pragmas like
+    // `# lars: disable=implicit-cast,type-error`. A pragma after code only
+    // covers its own line; one on a line by itself covers everything up to a
+    // matching `# lars: enable=...`. Unknown codes are ignored.
+    fn skip_comment(&mut self) {
+        let begin = self.index;
+        while !self.at_end() && self.cur() != b'\n' {
+            self.index += 1;
+        }
+
+        let text = self.substr(begin + 1, self.index).trim();
+        let pragma = match text.strip_prefix("lars:") {
+            Some(pragma) => pragma.trim(),
+            None => return,
+        };
+        let (enable, names) = if let Some(names) = pragma.strip_prefix("disable=") {
+            (false, names)
+        } else if let Some(names) = pragma.strip_prefix("enable=") {
+            (true, names)
+        } else {
+            return;
+        };
+
+        let before = &self.data[..begin as usize];
+        let line_begin = before
+            .iter()
+            .rposition(|&c| c == b'\n')
+            .map_or(0, |idx| idx + 1);
+        let own_line = before[line_begin..]
+            .iter()
+            .all(|&c| c == b' ' || c == b'\t');
+        for name in names.split(',') {
+            let code = match Code::from_name(name.trim()) {
+                Some(code) => code,
+                None => continue,
+            };
+
+            if !own_line {
+                if !enable {
+                    self.silenced.add(code, newr(line_begin as u32, self.index));
+                }
+            } else if enable {
+                if let Some(since) = self.disabled_since.remove(&code) {
+                    self.silenced.add(code, newr(since, begin));
+                }
+            } else {
+                self.disabled_since.entry(code).or_insert(begin);
+            }
+        }
+    }
+
+    fn peek_is_digit(&self) -> bool {
+        return match self.data.get(self.index as usize + 1) {
+            Some(c) => c.is_ascii_digit(),
+            Option::None => false,
+        };
+    }
+
+    // Python's numeric literals: decimal, hex, octal and binary integers, and
+    // floats with a fraction, an exponent or both. Integers too big for a u64
+    // saturate; the type checker reports anything that doesn't fit an int.
+    fn lex_number(&mut self) -> Token {
+        let begin = self.index;
+        let radix = match (self.cur(), self.data.get(begin as usize + 1)) {
+            (b'0', Some(b'x' | b'X')) => 16,
+            (b'0', Some(b'o' | b'O')) => 8,
+            (b'0', Some(b'b' | b'B')) => 2,
+            _ => 10,
+        };
+
+        let mut valid = true;
+        let mut float = false;
+        if radix != 10 {
+            self.index += 2;
+            valid &= self.eat_digits(radix, true);
+        } else {
+            if self.cur() != b'.' {
+                valid &= self.eat_digits(10, false);
+            }
+            if self.eat(b'.') {
+                float = true;
+                if !self.at_end() && self.cur().is_ascii_digit() {
+                    valid &= self.eat_digits(10, false);
+                }
+            }
+            if self.eat(b'e') || self.eat(b'E') {
+                float = true;
+                let _ = self.eat(b'+') || self.eat(b'-');
+                valid &= self.eat_digits(10, false);
+            }
+        }
+
+        // Something like `12abc` is one bad literal rather than two tokens
+        while !self.at_end() && (self.cur().is_ascii_alphanumeric() || self.cur() == b'_') {
+            self.index += 1;
+            valid = false;
+        }
+
+        let end = NonZeroU32::new(self.index).unwrap();
+        let text = self.substr(begin, self.index).replace('_', "");
+        let leading_zero =
+            !float && radix == 10 && text.starts_with('0') && text.bytes().any(|c| c != b'0');
+        if !valid || leading_zero {
+            let message = if valid {
+                "leading zeros in decimal integer literals are not permitted"
+            } else {
+                "invalid numeric literal"
+            };
+            self.errors.push(Error {
+                location: newr(begin, self.index),
+                message,
+                code: Code::Syntax,
+            });
+        }
+
+        if float {
+            let value = if valid { text.parse().unwrap() } else { 0.0 };
+            return Token::FloatingPoint { value, begin, end };
+        }
+
+        let digits = if radix == 10 { &text[..] } else { &text[2..] };
+        let value = if valid {
+            u64::from_str_radix(digits, radix).unwrap_or(u64::MAX)
+        } else {
+            0
+        };
+        return Token::Integer { value, begin, end };
+    }
+
+    // Consumes digits and underscores, returning whether there was at least one
+    // digit and every underscore came between two of them. Right after a radix
+    // prefix, an underscore can come first.
+    fn eat_digits(&mut self, radix: u32, after_prefix: bool) -> bool {
+        let mut valid = true;
+        let mut any = false;
+        let mut after_digit = after_prefix;
+        while !self.at_end() {
+            let c = self.cur();
+            if c == b'_' {
+                valid &= after_digit;
+                after_digit = false;
+            } else if (c as char).is_digit(radix) {
+                any = true;
+                after_digit = true;
+            } else {
+                break;
+            }
+            self.index += 1;
+        }
+        return valid && any && after_digit;
+    }
+
+    fn open_bracket(&mut self) {
+        self.index += 1;
+        self.bracket_depth += 1;
+    }
+
+    // Unbalanced closing brackets are left for the parser to complain about
+    fn close_bracket(&mut self) {
+        self.index += 1;
+        self.bracket_depth = self.bracket_depth.saturating_sub(1);
+    }
+
     fn next_dedent(&mut self) -> Token {
         let prev_indent = *self.indent_stack.last().unwrap();
         if self.indent_level < prev_indent {
@@ -242,17 +575,29 @@ impl<'a> Lexer<'a> {
 
             let ret_val = match self.cur() {
                 b'(' => {
-                    self.index += 1;
-                    self.paren_count += 1;
+                    self.open_bracket();
                     Token::LParen(self.index - 1)
                 }
                 b')' => {
-                    self.index += 1;
-                    if self.paren_count != 0 {
-                        self.paren_count -= 1;
-                    }
+                    self.close_bracket();
                     Token::RParen(self.index - 1)
                 }
+                b'[' => {
+                    self.open_bracket();
+                    Token::LBracket(self.index - 1)
+                }
+                b']' => {
+                    self.close_bracket();
+                    Token::RBracket(self.index - 1)
+                }
+                b'{' => {
+                    self.open_bracket();
+                    Token::LBrace(self.index - 1)
+                }
+                b'}' => {
+                    self.close_bracket();
+                    Token::RBrace(self.index - 1)
+                }
                 b'+' => {
                     self.index += 1;
                     Token::Plus(self.index - 1)
@@ -260,32 +605,90 @@ impl<'a> Lexer<'a> {
                 b'-' => {
                     let begin = self.index;
                     self.index += 1;
-                    if self.cur() == b'>' {
-                        self.index += 1;
+                    if self.eat(b'>') {
                         Token::Arrow(begin)
                     } else {
                         Token::Dash(begin)
                     }
                 }
+                b'*' => {
+                    let begin = self.index;
+                    self.index += 1;
+                    if self.eat(b'*') {
+                        Token::DoubleStar(begin)
+                    } else {
+                        Token::Star(begin)
+                    }
+                }
+                b'/' => {
+                    let begin = self.index;
+                    self.index += 1;
+                    if self.eat(b'/') {
+                        Token::DoubleSlash(begin)
+                    } else {
+                        Token::Slash(begin)
+                    }
+                }
+                b'%' => {
+                    self.index += 1;
+                    Token::Percent(self.index - 1)
+                }
+                b'<' => {
+                    let begin = self.index;
+                    self.index += 1;
+                    if self.eat(b'=') {
+                        Token::LessEqual(begin)
+                    } else {
+                        Token::Less(begin)
+                    }
+                }
+                b'>' => {
+                    let begin = self.index;
+                    self.index += 1;
+                    if self.eat(b'=') {
+                        Token::GreaterEqual(begin)
+                    } else {
+                        Token::Greater(begin)
+                    }
+                }
+                b'!' if self.data.get(self.index as usize + 1) == Some(&b'=') => {
+                    self.index += 2;
+                    Token::NotEqual(self.index - 2)
+                }
                 b',' => {
                     self.index += 1;
                     Token::Comma(self.index - 1)
                 }
                 b'=' => {
+                    let begin = self.index;
                     self.index += 1;
-                    Token::Equal(self.index - 1)
+                    if self.eat(b'=') {
+                        Token::EqualEqual(begin)
+                    } else {
+                        Token::Equal(begin)
+                    }
                 }
                 b':' => {
                     self.index += 1;
                     Token::Colon(self.index - 1)
                 }
+                b'.' if self.peek_is_digit() => self.lex_number(),
                 b'.' => {
                     self.index += 1;
                     Token::Dot(self.index - 1)
                 }
+                b'#' => {
+                    self.skip_comment();
+                    continue;
+                }
+                // A backslash at the end of a line joins it with the next one
+                b'\\' if self.data.get(self.index as usize + 1) == Some(&b'\n') => {
+                    self.index += 2;
+                    continue;
+                }
                 b'\n' => {
                     self.index += 1;
-                    if self.paren_count == 0 {
+                    if self.bracket_depth == 0 {
                         self.state = LexerState::Indentation;
                         return Token::Newline(self.index - 1);
                     }
@@ -329,41 +732,24 @@ impl<'a> Lexer<'a> {
                         tok
                     }
                 }
-                b'0' | b'1' | b'2' | b'3' | b'4' | b'5' | b'6' | b'7' | b'8' | b'9' => {
-                    let begin = self.index;
-                    while !self.at_end() && self.cur() <= b'9' && self.cur() >= b'0' {
-                        self.index += 1;
-                    }
-
-                    if !self.at_end() && self.cur() == b'.' {
-                        self.index += 1;
-                        while !self.at_end() && self.cur() <= b'9' && self.cur() >= b'0' {
-                            self.index += 1;
-                        }
-                        Token::FloatingPoint {
-                            value: self.substr(begin, self.index).parse().unwrap(),
-                            begin,
-                            end: NonZeroU32::new(self.index).unwrap(),
-                        }
-                    } else {
-                        Token::Integer {
-                            value: self.substr(begin, self.index).parse().unwrap(),
-                            begin,
-                            end: NonZeroU32::new(self.index).unwrap(),
-                        }
-                    }
-                }
+                b'0'..=b'9' => self.lex_number(),
                 c => {
-                    if (c as char).is_alphabetic() {
+                    if (c as char).is_alphabetic() || c == b'_' {
                         break;
                     } else {
-                        println!(
-                            "Warning: unknown character '{}' at location: {}",
-                            c as char, self.index
-                        );
+                        // The rest of a multi-byte character is part of it
+                        let begin = self.index;
                         self.index += 1;
+                        while !self.at_end() && self.cur() & 0xc0 == 0x80 {
+                            self.index += 1;
+                        }
+                        self.errors.push(Error {
+                            location: newr(begin, self.index),
+                            message: "unknown character",
+                            code: Code::Syntax,
+                        });
                         Token::Unknown {
-                            begin: self.index - 1,
+                            begin,
                             end: self.index,
                         }
                     }
@@ -377,7 +763,7 @@ impl<'a> Lexer<'a> {
         }
 
         let begin = self.index;
-        while !self.at_end() && (self.cur() as char).is_alphanumeric() {
+        while !self.at_end() && ((self.cur() as char).is_alphanumeric() || self.cur() == b'_') {
             self.index += 1;
         }
 
@@ -389,7 +775,14 @@ impl<'a> Lexer<'a> {
             "else" => Token::Else(begin),
             "elif" => Token::Elif(begin),
             "while" => Token::While(begin),
+            "for" => Token::For(begin),
+            "class" => Token::Class(begin),
+            "in" => Token::In(begin),
             "break" => Token::Break(begin),
+            "continue" => Token::Continue(begin),
+            "and" => Token::And(begin),
+            "or" => Token::Or(begin),
+            "not" => Token::Not(begin),
             "None" => Token::None(begin),
             "True" => Token::True(begin),
             "False" => Token::False(begin),
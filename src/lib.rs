@@ -0,0 +1,330 @@
+use std::io::Write;
+
+extern crate codespan_reporting;
+
+pub mod assembler;
+pub mod builtins;
+pub mod flow;
+pub mod inference;
+pub mod lexer;
+pub mod parser;
+pub mod printer;
+pub mod repl;
+pub mod runtime;
+pub mod syntax_tree;
+pub mod type_checker;
+pub mod util;
+
+use codespan_reporting::diagnostic::{Diagnostic, Label, Severity};
+
+// How far the pipeline goes, in order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    Parse,
+    Check,
+    Assemble,
+    Run,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Emit {
+    Tokens,
+    Ast,
+    Typed,
+    Bytecode,
+}
+
+impl Emit {
+    pub fn parse(name: &str) -> Option<Emit> {
+        return match name {
+            "tokens" => Some(Emit::Tokens),
+            "ast" => Some(Emit::Ast),
+            "typed" => Some(Emit::Typed),
+            "bytecode" => Some(Emit::Bytecode),
+            _ => None,
+        };
+    }
+
+    // The stage that has to finish before this can be printed
+    pub fn stage(self) -> Stage {
+        return match self {
+            Emit::Tokens | Emit::Ast => Stage::Parse,
+            Emit::Typed => Stage::Check,
+            Emit::Bytecode => Stage::Assemble,
+        };
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Options {
+    pub defer_errors: bool,
+    pub warnings_as_errors: bool,
+    pub emit: Option<Emit>,
+    pub last_stage: Stage,
+}
+
+impl Options {
+    pub fn new(last_stage: Stage) -> Self {
+        return Self {
+            defer_errors: false,
+            warnings_as_errors: false,
+            emit: None,
+            last_stage,
+        };
+    }
+}
+
+// A compiled module, ready to run
+pub struct Program {
+    pub ops: Vec<runtime::Opcode>,
+    pub assembler: assembler::Assembler,
+    pub names: Vec<String>,
+    file_id: usize,
+    source: String,
+    // Type errors that were deferred, by their index in the traps
+    errors: Vec<(util::CRange, util::Code, String)>,
+}
+
+pub fn compile(file_id: usize, source: &str) -> Result<Program, Vec<Diagnostic<usize>>> {
+    let options = Options::new(Stage::Assemble);
+    let host = builtins::Host::new();
+    let mut void = util::Void::new();
+    let program = compile_with(file_id, source, &host, &options, &mut void, &mut |_| {})?;
+    return Ok(program.expect("assembly was requested"));
+}
+
+// Runs the pipeline up to `options.last_stage`, with `host`'s functions
+// available as builtins, writing whatever `options.emit` asks for to `output`.
+// Fatal diagnostics are returned as errors, and warnings are passed to
+// `report` unless `warnings_as_errors` makes them fatal too. If
+// `defer_errors` is set, type errors are passed to `report` as well and only
+// become fatal if execution reaches the code that caused them. There's only a
+// program if the pipeline gets as far as assembling one.
+pub fn compile_with(
+    file_id: usize,
+    source: &str,
+    host: &builtins::Host,
+    options: &Options,
+    output: &mut dyn Write,
+    report: &mut dyn FnMut(Diagnostic<usize>),
+) -> Result<Option<Program>, Vec<Diagnostic<usize>>> {
+    let mut buckets = util::Buckets::new();
+    let result = compile_in(&mut buckets, file_id, source, host, options, output, report);
+    buckets.drop();
+    return result;
+}
+
+fn compile_in<'b>(
+    buckets: &mut util::Buckets<'b>,
+    file_id: usize,
+    source: &str,
+    host: &builtins::Host,
+    options: &Options,
+    output: &mut dyn Write,
+    report: &mut dyn FnMut(Diagnostic<usize>),
+) -> Result<Option<Program>, Vec<Diagnostic<usize>>> {
+    if options.emit == Some(Emit::Tokens) {
+        printer::print_tokens(output, source).expect("why did this fail?");
+    }
+
+    let (id_list, _) = builtins::builtin_names(host);
+    let lexer = lexer::Lexer::with_names(source, id_list);
+    let mut parser = parser::Parser::with_lexer(buckets, lexer);
+    let parse_result = parser.try_parse_program();
+    let silenced = parser.lexer.take_silenced();
+    let names: Vec<String> = parser
+        .lexer
+        .id_list
+        .iter()
+        .map(|&name| name.into())
+        .collect();
+
+    let program = match parse_result {
+        Ok(p) => buckets.add_array(p),
+        Err(errors) => return Err(error_diagnostics(file_id, &errors)),
+    };
+
+    if options.emit == Some(Emit::Ast) {
+        printer::print_ast(output, &names, program).expect("why did this fail?");
+    }
+    if options.last_stage == Stage::Parse {
+        return Ok(None);
+    }
+
+    let mut t = type_checker::TypeChecker::new(buckets);
+    t.set_silenced(silenced);
+    let (program, errors) = t.check_program(host, program);
+    let warnings = t.take_warnings();
+    let silenced = t.take_silenced();
+
+    // Silenced errors aren't reported, but still trap if they're reached, like
+    // deferred ones
+    let reported: Vec<util::Error> = errors
+        .iter()
+        .filter(|e| !silenced.contains(e.code, e.location))
+        .copied()
+        .collect();
+    if options.warnings_as_errors && !warnings.is_empty() {
+        let mut diagnostics = error_diagnostics(file_id, &[reported, warnings].concat());
+        for diagnostic in &mut diagnostics {
+            diagnostic.severity = Severity::Error;
+        }
+        return Err(diagnostics);
+    }
+
+    for diagnostic in error_diagnostics(file_id, &warnings) {
+        report(diagnostic);
+    }
+    if !reported.is_empty() {
+        if !options.defer_errors {
+            return Err(error_diagnostics(file_id, &reported));
+        }
+
+        for diagnostic in error_diagnostics(file_id, &reported) {
+            report(diagnostic);
+        }
+    }
+
+    if options.emit == Some(Emit::Typed) {
+        printer::print_typed(output, &names, &program).expect("why did this fail?");
+    }
+    if options.last_stage == Stage::Check {
+        return Ok(None);
+    }
+
+    let mut asmer = assembler::Assembler::new();
+    let ops = asmer.assemble_program(program);
+    if options.emit == Some(Emit::Bytecode) {
+        printer::print_bytecode(output, &names, &asmer, &ops).expect("why did this fail?");
+    }
+
+    return Ok(Some(Program {
+        ops,
+        assembler: asmer,
+        names,
+        file_id,
+        source: source.into(),
+        errors: errors
+            .iter()
+            .map(|e| (e.location, e.code, String::from(e.message)))
+            .collect(),
+    }));
+}
+
+impl Program {
+    // The runtime needs to have the host the program was compiled with
+    // installed
+    pub fn run<Out: Write>(
+        &self,
+        run: &mut runtime::Runtime<Out>,
+    ) -> Result<(), Vec<Diagnostic<usize>>> {
+        let file_id = self.file_id;
+        match run.run(&self.ops) {
+            Ok(()) => return Ok(()),
+            Err(runtime::RuntimeError::Trap(error)) => {
+                let (location, code, message) = &self.errors[error as usize];
+                let traceback = traceback(
+                    &self.assembler,
+                    run,
+                    &self.names,
+                    &self.source,
+                    Some(*location),
+                );
+                return Err(vec![Diagnostic::error()
+                    .with_code(code.name())
+                    .with_message("execution reached code that failed to compile")
+                    .with_labels(vec![
+                        Label::primary(file_id, location.into_range()).with_message(message)
+                    ])
+                    .with_notes(vec![traceback])]);
+            }
+            Err(runtime::RuntimeError::Fault { pc, message }) => {
+                let mut diagnostic = Diagnostic::error()
+                    .with_code(util::Code::Runtime.name())
+                    .with_message(message);
+                if let Some(location) = fault_location(&self.assembler, run, pc) {
+                    diagnostic = diagnostic
+                        .with_labels(vec![Label::primary(file_id, location.into_range())]);
+                }
+                let location = self.assembler.source_map.get(&(pc as u32)).copied();
+                let traceback =
+                    traceback(&self.assembler, run, &self.names, &self.source, location);
+                return Err(vec![diagnostic.with_notes(vec![traceback])]);
+            }
+        }
+    }
+}
+
+pub fn error_diagnostic(file_id: usize, e: &util::Error) -> Diagnostic<usize> {
+    let severity = match e.severity() {
+        util::Severity::Error => Severity::Error,
+        util::Severity::Warning => Severity::Warning,
+    };
+    return Diagnostic::new(severity)
+        .with_code(e.code.name())
+        .with_message(e.message)
+        .with_labels(vec![Label::primary(file_id, e.location.into_range())]);
+}
+
+pub fn error_diagnostics(file_id: usize, errors: &[util::Error]) -> Vec<Diagnostic<usize>> {
+    let mut errors: Vec<&util::Error> = errors.iter().collect();
+    errors.sort_by_key(|e| e.location.start);
+    return errors
+        .into_iter()
+        .map(|e| error_diagnostic(file_id, e))
+        .collect();
+}
+
+// Ops inside builtins don't have a source location, so faults there are
+// reported at the innermost call that has one.
+pub(crate) fn fault_location<Out: Write>(
+    asmer: &assembler::Assembler,
+    run: &runtime::Runtime<Out>,
+    pc: usize,
+) -> Option<util::CRange> {
+    if let Some(&location) = asmer.source_map.get(&(pc as u32)) {
+        return Some(location);
+    }
+
+    // `fp_ra_stack` holds (return address, frame pointer) pairs
+    for &return_address in run.fp_ra_stack.iter().rev().skip(1).step_by(2) {
+        if let Some(&location) = asmer.source_map.get(&(return_address as u32 - 1)) {
+            return Some(location);
+        }
+    }
+    return None;
+}
+
+// Lists every active frame that has a source location, innermost last, like
+// Python does. `location` is where the innermost frame stopped, if it's known;
+// the other frames stopped at the call to the next one.
+pub(crate) fn traceback<Out: Write>(
+    asmer: &assembler::Assembler,
+    run: &runtime::Runtime<Out>,
+    names: &[String],
+    input: &str,
+    location: Option<util::CRange>,
+) -> String {
+    let mut frames = Vec::new();
+    for pair in run.fp_ra_stack.chunks(2).skip(1) {
+        let call = pair[0] as u32 - 1;
+        frames.push((call, asmer.source_map.get(&call).copied()));
+    }
+    frames.push((run.pc as u32, location));
+
+    let mut traceback = String::from("Traceback (most recent call last):");
+    for (address, location) in frames {
+        let location = match location {
+            Some(location) => location,
+            None => continue,
+        };
+
+        let line = input[..location.start as usize].matches('\n').count() + 1;
+        let function = match asmer.function_name_at(address) {
+            Some(name) => names[name as usize].as_str(),
+            None => "<module>",
+        };
+        traceback += &format!("\n  line {}, in {}", line, function);
+    }
+    return traceback;
+}
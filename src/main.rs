@@ -1,140 +1,478 @@
-#![allow(unused_variables)]
-#![allow(dead_code)]
-
 use std::env;
 use std::fs::read_to_string;
 use std::io::Write;
 
 extern crate codespan_reporting;
+extern crate lars;
 
-mod assembler;
-mod builtins;
-mod lexer;
-mod parser;
-mod runtime;
-mod syntax_tree;
-mod type_checker;
-mod util;
-
-use codespan_reporting::diagnostic::{Diagnostic, Label};
+use codespan_reporting::diagnostic::Diagnostic;
+#[cfg(test)]
+use codespan_reporting::diagnostic::Severity;
 use codespan_reporting::files::SimpleFiles;
 use codespan_reporting::term::termcolor::{ColorChoice, StandardStream};
+use lars::builtins::Host;
+use lars::{repl, runtime, util, Emit, Options, Stage};
+
+// How a test file went: what it printed, what was reported along the way,
+// and what it ended with
+#[cfg(test)]
+struct TestRun {
+    output: String,
+    reported: Vec<Diagnostic<usize>>,
+    result: Result<runtime::HeapStats, Vec<Diagnostic<usize>>>,
+}
 
-fn run_on_file<'a, 'b>(
-    stdout: impl Write,
-    stderr: impl Write,
-    buckets: &mut util::Buckets<'b>,
-    files: &mut SimpleFiles<&'a str, &'b str>,
-    filename: &'a str,
-) -> Result<(), Diagnostic<usize>> {
-    let input = buckets.add_str(&read_to_string(filename).unwrap());
-    let file_id = files.add(filename, input);
+#[cfg(test)]
+fn run_test_file(filename: &str, options: &Options) -> TestRun {
+    let input = read_to_string(filename).unwrap();
+    let mut output = util::StringWriter::new();
+    let mut reported = Vec::new();
+    let result = run_on_string(&mut output, 0, &input, options, &mut |diagnostic| {
+        reported.push(diagnostic)
+    });
 
-    return run_on_string(stdout, stderr, buckets, file_id, &input);
+    return TestRun {
+        output: output.into_string(),
+        reported,
+        result,
+    };
 }
 
-fn run_on_string<'b>(
-    output: impl Write,
-    mut stderr: impl Write,
-    buckets: &mut util::Buckets<'b>,
+// Compiles and runs the file as far as `options` asks, writing anything
+// emitted to `output` ahead of what the program prints
+fn run_on_string(
+    mut output: impl Write,
     file_id: usize,
     input: &str,
-) -> Result<(), Diagnostic<usize>> {
-    let mut parser = parser::Parser::new(buckets, input);
-    let parse_result = parser.try_parse_program();
-
-    let program = match parse_result {
-        Ok(p) => buckets.add_array(p),
-        Err(e) => {
-            return Err(Diagnostic::error()
-                .with_message(e.message)
-                .with_labels(vec![Label::primary(
-                    file_id,
-                    (e.location.start as usize)..(e.location.end as usize),
-                )]));
+    options: &Options,
+    report: &mut dyn FnMut(Diagnostic<usize>),
+) -> Result<runtime::HeapStats, Vec<Diagnostic<usize>>> {
+    let program =
+        match lars::compile_with(file_id, input, &Host::new(), options, &mut output, report)? {
+            Some(program) if options.last_stage == Stage::Run => program,
+            _ => return Ok(runtime::HeapStats::default()),
+        };
+
+    let mut run = runtime::Runtime::new(output);
+    program.run(&mut run)?;
+    return Ok(run.heap_stats());
+}
+
+// Every `test_data/**/*.py` is run, and what it prints is compared with the
+// `.out` file next to it. Files that are expected to warn or fail also have a
+// `.err` file, which lists the diagnostics they should produce, one per line,
+// as `line:col-line:col: severity[code]: message` for the primary label.
+// Missing files expect nothing. Set `LARS_BLESS` to rewrite both from the current behavior instead.
+#[test]
+fn test_golden_files() {
+    let bless = env::var_os("LARS_BLESS").is_some();
+    let mut paths = Vec::new();
+    find_sources(std::path::Path::new("test_data"), &mut paths);
+    paths.sort();
+
+    let mut mismatches = Vec::new();
+    for path in paths {
+        let filename = path.to_str().expect("test data should have UTF-8 names");
+        let (output, errors) = run_golden_file(filename);
+
+        for (extension, actual) in &[(".out", output), (".err", errors)] {
+            let expected_path = String::from(filename) + extension;
+            let expected = read_to_string(&expected_path).unwrap_or_default();
+            if *actual == expected {
+                continue;
+            }
+
+            if !bless {
+                mismatches.push(format!(
+                    "{}:\n--- expected\n{}--- actual\n{}",
+                    expected_path, expected, actual
+                ));
+            } else if actual.is_empty() {
+                std::fs::remove_file(&expected_path).expect("why did this fail?");
+            } else {
+                std::fs::write(&expected_path, actual).expect("why did this fail?");
+            }
         }
-    };
+    }
+
+    assert!(mismatches.is_empty(), "\n{}", mismatches.join("\n"));
+}
 
-    write!(stderr, "{:?}\n\n", program).expect("why did this fail?");
-
-    let mut t = type_checker::TypeChecker::new(buckets);
-    let program = match t.check_program(program) {
-        Ok(p) => p,
-        Err(e) => {
-            return Err(Diagnostic::error()
-                .with_message(e.message)
-                .with_labels(vec![Label::primary(
-                    file_id,
-                    (e.location.start as usize)..(e.location.end as usize),
-                )]));
+#[cfg(test)]
+fn find_sources(dir: &std::path::Path, paths: &mut Vec<std::path::PathBuf>) {
+    for entry in std::fs::read_dir(dir).expect("why did this fail?") {
+        let path = entry.expect("why did this fail?").path();
+        if path.is_dir() {
+            find_sources(&path, paths);
+        } else if path.extension() == Some(std::ffi::OsStr::new("py")) {
+            paths.push(path);
         }
-    };
+    }
+}
 
-    write!(stderr, "{:?}\n\n", program).expect("why did this fail?");
+// Returns what the file printed and the diagnostics it failed with, in the
+// formats of the `.out` and `.err` files
+#[cfg(test)]
+fn run_golden_file(filename: &str) -> (String, String) {
+    let input = read_to_string(filename).unwrap();
+    let run = run_test_file(filename, &Options::new(Stage::Run));
+    let mut diagnostics = run.reported;
+    diagnostics.extend(run.result.err().unwrap_or_default());
 
-    let mut asmer = assembler::Assembler::new();
-    let ops = asmer.assemble_program(program);
-    buckets.drop();
-    write!(stderr, "{:?}\n\n", ops).expect("why did this fail?");
-    let mut run = runtime::Runtime::new(output);
-    run.run(&ops);
+    let mut errors = String::new();
+    for diagnostic in diagnostics {
+        let span = match diagnostic.labels.first() {
+            Some(label) => {
+                let (start_line, start_col) = util::line_col(&input, label.range.start as u32);
+                let (end_line, end_col) = util::line_col(&input, label.range.end as u32);
+                format!("{}:{}-{}:{}", start_line, start_col, end_line, end_col)
+            }
+            None => String::from("?"),
+        };
+        let severity = match diagnostic.severity {
+            Severity::Warning => "warning",
+            _ => "error",
+        };
+        let code = diagnostic.code.unwrap_or_default();
+        errors += &format!("{}: {}[{}]: {}\n", span, severity, code, diagnostic.message);
+    }
 
-    return Ok(());
+    return (run.output, errors);
 }
 
-fn test_file_should_succeed(filename: &str) {
-    let writer = StandardStream::stderr(ColorChoice::Always);
-    let config = codespan_reporting::term::Config::default();
+// What these print is checked by the golden test
+#[test]
+fn test_garbage_collection() {
+    let run = run_test_file("test_data/garbage.py", &Options::new(Stage::Run));
+    let stats = run.result.expect("expected program to run");
+    assert!(stats.collections > 0);
+    assert!(stats.freed_words > 500_000);
+    assert!(stats.peak_heap_words <= 2 * runtime::MIN_COLLECTION_THRESHOLD);
+}
+
+#[test]
+fn test_unboxed_numbers() {
+    let run = run_test_file("test_data/numeric_loop.py", &Options::new(Stage::Run));
+    let stats = run.result.expect("expected program to run");
+    assert_eq!(stats.collections, 0);
+    assert!(stats.peak_heap_words < 100);
+}
 
-    let mut buckets = util::Buckets::new();
-    let mut files = SimpleFiles::new();
+#[test]
+fn test_traceback() {
+    let run = run_test_file("test_data/traceback.py", &Options::new(Stage::Run));
+    let diagnostics = run.result.expect_err("expected execution to fail");
+    assert_eq!(
+        diagnostics[0].notes,
+        vec![concat!(
+            "Traceback (most recent call last):\n",
+            "  line 8, in <module>\n",
+            "  line 3, in down\n",
+            "  line 3, in down\n",
+            "  line 3, in down\n",
+            "  line 5, in down",
+        )]
+    );
+}
+
+#[test]
+fn test_repl() {
     let mut output = util::StringWriter::new();
+    let mut messages = Vec::new();
+    let input = read_to_string("test_data/repl.txt").unwrap();
 
-    match run_on_file(
-        &mut output,
-        util::Void::new(),
-        &mut buckets,
-        &mut files,
-        filename,
-    ) {
-        Err(diagnostic) => {
-            codespan_reporting::term::emit(&mut writer.lock(), &config, &files, &diagnostic)
-                .expect("why did this fail?");
-            panic!();
-        }
-        _ => {}
+    let mut repl = repl::Repl::new(&mut output);
+    repl.run(input.as_bytes(), false, &mut |_, diagnostic| {
+        messages.push(diagnostic.message.clone())
+    });
+    drop(repl);
+
+    assert_eq!(
+        output.into_string(),
+        read_to_string("test_data/repl.txt.out").unwrap()
+    );
+    assert_eq!(
+        messages,
+        vec![
+            "incompatible types for arithmetic operation",
+            "referenced name doesn't exist",
+            "integer division by zero",
+            "execution reached code that failed to compile",
+        ]
+    );
+}
+
+#[test]
+fn test_emit() {
+    for &(emit, last_stage, extension) in &[
+        (Emit::Ast, Stage::Parse, ".ast"),
+        (Emit::Typed, Stage::Check, ".typed"),
+        (Emit::Bytecode, Stage::Assemble, ".bytecode"),
+    ] {
+        let options = Options {
+            emit: Some(emit),
+            ..Options::new(last_stage)
+        };
+        let run = run_test_file("test_data/emit.py", &options);
+        run.result.expect("expected compilation to succeed");
+
+        let expected = read_to_string(String::from("test_data/emit.py") + extension).unwrap();
+        assert_eq!(run.output, expected);
+    }
+}
+
+#[test]
+fn test_library() {
+    let source = "def twice(a: int) -> int:\n    return a * 2\n\nprint(twice(21))\n";
+    let program = lars::compile(0, source).expect("expected compilation to succeed");
+    let mut output = util::StringWriter::new();
+    let mut run = runtime::Runtime::new(&mut output);
+    program.run(&mut run).expect("expected program to run");
+    drop(run);
+    assert_eq!(output.into_string(), "42\n");
+
+    let errors = lars::compile(0, "x: int = \"a\"\n").err().unwrap();
+    assert_eq!(errors[0].message, "value is wrong type");
+}
+
+#[test]
+fn test_host_functions() {
+    use lars::runtime::Value;
+    use lars::syntax_tree::Type;
+
+    let mut host = Host::new();
+    host.register(
+        "clamp",
+        &[Type::Int, Type::Int, Type::Int],
+        Type::Int,
+        |args| match args {
+            [Value::Int(x), Value::Int(lo), Value::Int(hi)] => Ok(Value::Int(*x.max(lo).min(hi))),
+            _ => Err("clamp needs three ints"),
+        },
+    );
+    host.register("shout", &[Type::Str], Type::Str, |args| match args {
+        [Value::Str(s)] => Ok(Value::Str(s.to_uppercase() + "!")),
+        [Value::None] => Ok(Value::None),
+        _ => unreachable!(),
+    });
+    host.register("refuse", &[], Type::None, |_| Err("the host refused"));
+    host.register("broken", &[], Type::Int, |_| {
+        Ok(Value::Str(String::from("1")))
+    });
+
+    let source = concat!(
+        "print(clamp(15, 0, 10))\n",
+        "print(clamp(0 - 5, 0, 10) + 1)\n",
+        "print(shout(\"hi\"))\n",
+        "print(shout(None))\n",
+        "refuse()\n",
+    );
+    let mut output = util::StringWriter::new();
+    let options = Options::new(Stage::Assemble);
+    let program = lars::compile_with(0, source, &host, &options, &mut output, &mut |_| {})
+        .expect("expected compilation to succeed")
+        .unwrap();
+
+    let mut run = runtime::Runtime::new(&mut output);
+    run.install(&host);
+    let errors = program
+        .run(&mut run)
+        .expect_err("expected the host to refuse");
+    drop(run);
+    assert_eq!(output.into_string(), "10\n1\nHI!\nNone\n");
+    assert_eq!(errors[0].message, "the host refused");
+
+    // None can be passed for any argument, and the host has to cope with it
+    for (source, message) in [
+        ("clamp(None, 0, 10)\n", "clamp needs three ints"),
+        (
+            "print(broken() + 1)\n",
+            "host function returned the wrong type",
+        ),
+    ] {
+        let program = lars::compile_with(
+            0,
+            source,
+            &host,
+            &options,
+            &mut util::Void::new(),
+            &mut |_| {},
+        )
+        .expect("expected compilation to succeed")
+        .unwrap();
+        let mut run = runtime::Runtime::new(util::Void::new());
+        run.install(&host);
+        let errors = program.run(&mut run).expect_err("expected a fault");
+        assert_eq!(errors[0].message, message);
     }
 
-    let filename = String::from(filename);
-    assert!(output.to_string() == read_to_string(filename + ".out").expect("why did this fail?"));
+    let errors = lars::compile_with(
+        0,
+        "clamp(1, 2)\n",
+        &host,
+        &options,
+        &mut util::Void::new(),
+        &mut |_| {},
+    )
+    .err()
+    .unwrap();
+    assert_eq!(errors.len(), 1);
 }
 
 #[test]
-fn test_expr() {
-    test_file_should_succeed("test_data/expressions.py");
+fn test_warnings_as_errors() {
+    let options = Options {
+        warnings_as_errors: true,
+        ..Options::new(Stage::Run)
+    };
+    let run = run_test_file("test_data/implicit_casts.py", &options);
+    let errors = run.result.expect_err("expected warnings to be fatal");
+    assert!(run.reported.is_empty());
+    assert_eq!(errors.len(), 9);
+    assert!(errors.iter().all(|e| e.severity == Severity::Error));
 }
 
+#[test]
+fn test_deferred_errors() {
+    let options = Options {
+        defer_errors: true,
+        ..Options::new(Stage::Run)
+    };
+    let run = run_test_file("test_data/deferred_errors.py", &options);
+    let fatal = run
+        .result
+        .expect_err("expected execution to reach the trap");
+    let reported: Vec<&str> = run.reported.iter().map(|d| d.message.as_str()).collect();
+    assert_eq!(reported, vec!["value is wrong type"]);
+    assert_eq!(fatal.len(), 1);
+    assert_eq!(
+        fatal[0].message,
+        "execution reached code that failed to compile"
+    );
+    assert_eq!(run.output, "2\nstill running\n");
+}
+
+const USAGE: &str = "usage: lars [run|check|disasm|ast] [OPTION...] FILE...
+       lars                start an interactive session
+
+options:
+  --emit=tokens|ast|typed|bytecode  print a stage of compilation
+  --defer-errors                    only fail on type errors that are reached
+  --warnings-as-errors              fail on warnings too";
+
+// Exit codes: 0 on success, 1 if any file fails to compile or run, and 2 if
+// the arguments don't make sense
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() {
+        let writer = StandardStream::stderr(ColorChoice::Always);
+        let config = codespan_reporting::term::Config::default();
+        let mut repl = repl::Repl::new(std::io::stdout());
+        repl.run(std::io::stdin().lock(), true, &mut |files, diagnostic| {
+            codespan_reporting::term::emit(&mut writer.lock(), &config, files, diagnostic)
+                .expect("why did this fail?");
+        });
+        return;
+    }
+
+    let (options, filenames) = match parse_args(&args) {
+        Ok(parsed) => parsed,
+        Err(message) => {
+            eprintln!("error: {}\n{}", message, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    if !run_files(&options, &filenames) {
+        std::process::exit(1);
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<(Options, Vec<&str>), String> {
+    let mut args = args.iter().map(String::as_str).peekable();
+    let mut options = match args.peek() {
+        Some(&"run") => Options::new(Stage::Run),
+        Some(&"check") => Options::new(Stage::Check),
+        Some(&"disasm") => Options {
+            emit: Some(Emit::Bytecode),
+            ..Options::new(Stage::Assemble)
+        },
+        Some(&"ast") => Options {
+            emit: Some(Emit::Ast),
+            ..Options::new(Stage::Parse)
+        },
+        // A bare file is run
+        _ => Options::new(Stage::Run),
+    };
+    if let Some(&("run" | "check" | "disasm" | "ast")) = args.peek() {
+        args.next();
+    }
 
+    let mut filenames = Vec::new();
+    for arg in args {
+        if arg == "--defer-errors" {
+            options.defer_errors = true;
+        } else if arg == "--warnings-as-errors" {
+            options.warnings_as_errors = true;
+        } else if let Some(name) = arg.strip_prefix("--emit=") {
+            let emit = match Emit::parse(name) {
+                Some(emit) => emit,
+                None => return Err(format!("unknown stage `{}` to emit", name)),
+            };
+            options.emit = Some(emit);
+            options.last_stage = options.last_stage.max(emit.stage());
+        } else if arg.starts_with('-') {
+            return Err(format!("unknown option `{}`", arg));
+        } else {
+            filenames.push(arg);
+        }
+    }
+
+    if filenames.is_empty() {
+        return Err(String::from("no input files"));
+    }
+    return Ok((options, filenames));
+}
+
+// Returns whether every file succeeded
+fn run_files(options: &Options, filenames: &[&str]) -> bool {
     let writer = StandardStream::stderr(ColorChoice::Always);
     let config = codespan_reporting::term::Config::default();
 
-    for arg in args.iter().skip(1) {
+    let mut succeeded = true;
+    for &filename in filenames {
+        let input = match read_to_string(filename) {
+            Ok(input) => input,
+            Err(error) => {
+                eprintln!("error: couldn't read `{}`: {}", filename, error);
+                succeeded = false;
+                continue;
+            }
+        };
+
         let mut buckets = util::Buckets::new();
         let mut files = SimpleFiles::new();
-        match run_on_file(
+        let input: &str = buckets.add_str(&input);
+        let file_id = files.add(filename, input);
+        let result = run_on_string(
             std::io::stdout(),
-            std::io::stderr(),
-            &mut buckets,
-            &mut files,
-            arg,
-        ) {
-            Err(diagnostic) => {
+            file_id,
+            input,
+            options,
+            &mut |diagnostic| {
                 codespan_reporting::term::emit(&mut writer.lock(), &config, &files, &diagnostic)
-                    .expect("why did this fail?")
+                    .expect("why did this fail?");
+            },
+        );
+        if let Err(diagnostics) = result {
+            succeeded = false;
+            for diagnostic in diagnostics {
+                codespan_reporting::term::emit(&mut writer.lock(), &config, &files, &diagnostic)
+                    .expect("why did this fail?");
             }
-            _ => {}
         }
+
+        buckets.drop();
     }
+    return succeeded;
 }
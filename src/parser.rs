@@ -3,6 +3,44 @@ use crate::syntax_tree::*;
 use crate::util::*;
 use std::mem;
 
+const OR_PREC: u8 = 1;
+const AND_PREC: u8 = 2;
+const NOT_PREC: u8 = 3;
+const COMPARE_PREC: u8 = 4;
+const ADD_PREC: u8 = 5;
+const MUL_PREC: u8 = 6;
+const UNARY_PREC: u8 = 7;
+const POW_PREC: u8 = 8;
+
+fn binary_op(tok: Token) -> Option<(BinOp, u8)> {
+    use Token::*;
+    return match tok {
+        Or(_) => Some((BinOp::Or, OR_PREC)),
+        And(_) => Some((BinOp::And, AND_PREC)),
+        Plus(_) => Some((BinOp::Add, ADD_PREC)),
+        Dash(_) => Some((BinOp::Sub, ADD_PREC)),
+        Star(_) => Some((BinOp::Mul, MUL_PREC)),
+        Slash(_) => Some((BinOp::Div, MUL_PREC)),
+        DoubleSlash(_) => Some((BinOp::FloorDiv, MUL_PREC)),
+        Percent(_) => Some((BinOp::Mod, MUL_PREC)),
+        DoubleStar(_) => Some((BinOp::Pow, POW_PREC)),
+        _ => Option::None,
+    };
+}
+
+fn comparison_op(tok: Token) -> Option<CmpOp> {
+    use Token::*;
+    return match tok {
+        EqualEqual(_) => Some(CmpOp::Eq),
+        NotEqual(_) => Some(CmpOp::Ne),
+        Less(_) => Some(CmpOp::Lt),
+        LessEqual(_) => Some(CmpOp::Le),
+        Greater(_) => Some(CmpOp::Gt),
+        GreaterEqual(_) => Some(CmpOp::Ge),
+        _ => Option::None,
+    };
+}
+
 pub struct Parser<'a, 'b>
 where
     'b: 'a,
@@ -11,6 +49,9 @@ where
     pub lexer: Lexer<'a>,
     token: Token,
     token2: Token,
+    last: Option<Token>,
+    popped: usize,
+    errors: Vec<Error<'b>>,
 }
 
 impl<'a, 'b> Parser<'a, 'b>
@@ -18,7 +59,10 @@ where
     'b: 'a,
 {
     pub fn new(buckets: &'a mut Buckets<'b>, data: &'a str) -> Self {
-        let mut lexer = Lexer::new(data);
+        return Self::with_lexer(buckets, Lexer::new(data));
+    }
+
+    pub fn with_lexer(buckets: &'a mut Buckets<'b>, mut lexer: Lexer<'a>) -> Self {
         let token = lexer.next();
         let token2 = lexer.next();
 
@@ -27,6 +71,9 @@ where
             lexer,
             token,
             token2,
+            last: Option::None,
+            popped: 0,
+            errors: Vec::new(),
         };
     }
 
@@ -42,34 +89,115 @@ where
         let prev_token = self.token;
         self.token = self.token2;
         self.token2 = self.lexer.next();
+        self.last = Some(prev_token);
+        self.popped += 1;
         return prev_token;
     }
 
-    pub fn try_parse_program(&mut self) -> Result<Vec<Stmt<'b>>, Error<'b>> {
+    pub fn try_parse_program(&mut self) -> Result<Vec<Stmt<'b>>, Vec<Error<'b>>> {
         let mut stmts = Vec::new();
-        while match self.peek() {
-            Token::End(_) => false,
-            _ => true,
-        } {
-            stmts.push(self.try_parse_stmt()?);
+        while !matches!(self.peek(), Token::End(_)) {
+            if let Some(stmt) = self.parse_stmt_or_recover() {
+                stmts.push(stmt);
+            }
+        }
+
+        self.errors.extend(mem::take(&mut self.lexer.errors));
+        if !self.errors.is_empty() {
+            return Err(mem::take(&mut self.errors));
         }
         return Ok(stmts);
     }
 
+    fn parse_stmt_or_recover(&mut self) -> Option<Stmt<'b>> {
+        let popped = self.popped;
+        match self.try_parse_stmt() {
+            Ok(stmt) => return Some(stmt),
+            Err(e) => {
+                self.errors.push(Error {
+                    code: Code::Syntax,
+                    ..e
+                });
+                self.recover(popped);
+                return Option::None;
+            }
+        }
+    }
+
+    // Skips to the start of the next statement after a syntax error. If the
+    // statement that failed has an indented block, the block gets skipped too.
+    fn recover(&mut self, popped: usize) {
+        use Token::*;
+        if self.popped == popped {
+            // The statement didn't consume anything, so make sure we make
+            // progress
+            match self.peek() {
+                End(_) => return,
+                _ => {
+                    self.pop();
+                }
+            }
+        }
+
+        while !matches!(self.last, Some(Newline(_))) {
+            match self.peek() {
+                End(_) | Dedent(_) => return,
+                _ => {
+                    self.pop();
+                }
+            }
+        }
+
+        if let Indent { .. } = self.peek() {
+            let mut depth = 0;
+            loop {
+                match self.pop() {
+                    Indent { .. } => depth += 1,
+                    Dedent(_) => depth -= 1,
+                    End(_) => return,
+                    _ => {}
+                }
+
+                if depth == 0 {
+                    return;
+                }
+            }
+        }
+    }
+
+    // Loops run their `else` block when they finish without a `break`
+    fn try_parse_loop_else(&mut self) -> Result<&'b mut [Stmt<'b>], Error<'b>> {
+        match self.peek() {
+            Token::Else(_) => {
+                self.pop();
+                self.expect_colon()?;
+                self.expect_newline()?;
+                return self.try_parse_block();
+            }
+            _ => return Ok(self.buckets.add_array(vec![])),
+        }
+    }
+
     pub fn try_parse_stmt(&mut self) -> Result<Stmt<'b>, Error<'b>> {
         use Token::*;
         match self.peek() {
-            Break(_) => {
+            t @ Break(_) => {
+                self.pop();
+                self.expect_newline()?;
+                return Ok(Stmt::Break(t.view()));
+            }
+            t @ Continue(_) => {
                 self.pop();
                 self.expect_newline()?;
-                return Ok(Stmt::Break);
+                return Ok(Stmt::Continue(t.view()));
             }
-            Pass(_) => {
+            t @ Pass(_) => {
                 self.pop();
                 self.expect_newline()?;
-                return Ok(Stmt::Pass);
+                return Ok(Stmt::Pass(t.view()));
             }
             Def(_) => return self.try_parse_func(),
+            Class(_) => return self.try_parse_class(),
             Return(_) => {
                 self.pop();
                 let expr = self.try_parse_expr()?;
@@ -83,15 +211,7 @@ where
                 self.expect_colon()?;
                 self.expect_newline()?;
                 let block = self.try_parse_block()?;
-                let else_branch = match self.peek() {
-                    Else(_) => {
-                        self.pop();
-                        self.expect_colon()?;
-                        self.expect_newline()?;
-                        self.try_parse_block()?
-                    }
-                    _ => self.buckets.add_array(vec![]),
-                };
+                let else_branch = self.try_parse_loop_else()?;
 
                 let condition = self.buckets.add(condition);
                 return Ok(Stmt::While {
@@ -100,6 +220,32 @@ where
                     else_branch,
                 });
             }
+            For(_) => {
+                self.pop();
+                let (target, target_view) = match self.pop() {
+                    Ident { id, view } => (id, view),
+                    x => return err(x.view(), "expected loop variable"),
+                };
+                match self.pop() {
+                    In(_) => {}
+                    x => return err(x.view(), "expected 'in'"),
+                }
+
+                let iterable = self.try_parse_expr()?;
+                self.expect_colon()?;
+                self.expect_newline()?;
+                let block = self.try_parse_block()?;
+                let else_branch = self.try_parse_loop_else()?;
+
+                let iterable = self.buckets.add(iterable);
+                return Ok(Stmt::For {
+                    target,
+                    target_view,
+                    iterable,
+                    block,
+                    else_branch,
+                });
+            }
             If(_) => {
                 self.pop();
                 let mut conditioned_blocks = Vec::new();
@@ -140,7 +286,7 @@ where
         }
 
         if let Ident { id, view } = self.peek() {
-            if let Colon(cloc) = self.peek2() {
+            if let Colon(_) = self.peek2() {
                 self.pop();
                 self.pop();
                 let type_ident;
@@ -153,6 +299,7 @@ where
                     return Err(Error {
                         location: tok.view(),
                         message: "type needs to be identifier",
+                        code: Code::Syntax,
                     });
                 }
 
@@ -162,6 +309,7 @@ where
                         return Err(Error {
                             location: x.view(),
                             message: "expected equal sign after variable declaration",
+                            code: Code::Syntax,
                         })
                     }
                 }
@@ -182,7 +330,7 @@ where
         let expr = self.try_parse_expr()?;
         let expr = self.buckets.add(expr);
         match self.pop() {
-            Newline(loc2) => return Ok(Stmt::Expr(expr)),
+            Newline(_) => return Ok(Stmt::Expr(expr)),
             Equal(_) => match expr {
                 Expr::Ident { id, view } => {
                     let value = self.try_parse_expr()?;
@@ -193,6 +341,7 @@ where
                             return Err(Error {
                                 location: joinr(*view, x.view()),
                                 message: "statement needs to end in a newline",
+                                code: Code::Syntax,
                             })
                         }
                     }
@@ -208,10 +357,12 @@ where
                     member_view,
                 } => {
                     let value = self.try_parse_expr()?;
+                    self.expect_newline()?;
                     let value = self.buckets.add(value);
                     return Ok(Stmt::AssignMember {
-                        to: *parent,
-                        to_member: 0,
+                        to: parent,
+                        to_member: *member_id,
+                        to_member_view: *member_view,
                         value,
                     });
                 }
@@ -233,18 +384,17 @@ where
 
     fn try_parse_block(&mut self) -> Result<&'b mut [Stmt<'b>], Error<'b>> {
         match self.pop() {
-            Token::Indent { begin, end } => {}
+            Token::Indent { .. } => {}
             x => {
                 return err(x.view(), "unexpected token when parsing function signature");
             }
         }
 
         let mut stmts = Vec::new();
-        while match self.peek() {
-            Token::Dedent(_) => false,
-            _ => true,
-        } {
-            stmts.push(self.try_parse_stmt()?);
+        while !matches!(self.peek(), Token::Dedent(_) | Token::End(_)) {
+            if let Some(stmt) = self.parse_stmt_or_recover() {
+                stmts.push(stmt);
+            }
         }
 
         let stmts = self.buckets.add_array(stmts);
@@ -276,6 +426,7 @@ where
                 return Err(Error {
                     location: x.view(),
                     message: "unexpected token when parsing function arguments",
+                    code: Code::Syntax,
                 });
             }
         }
@@ -291,6 +442,7 @@ where
         loop {
             let start;
             let arg_name;
+            let mut end;
             match self.pop() {
                 Token::RParen(_) => {
                     break;
@@ -298,31 +450,30 @@ where
                 Token::Ident { id, view } => {
                     arg_name = id;
                     start = view.start;
+                    end = view.end;
                 }
                 x => {
                     return err(x.view(), "unexpected token when parsing function arguments");
                 }
             }
 
-            match self.pop() {
-                Token::Colon(_) => {}
-                x => {
-                    return err(x.view(), "unexpected token when parsing function arguments");
-                }
-            }
-
-            let end;
-            let type_name;
-            match self.pop() {
-                Token::Ident { id, view } => {
-                    type_name = id;
-                    end = view.end;
-                }
-                x => {
-                    return Err(Error {
-                        location: x.view(),
-                        message: "unexpected token when parsing function arguments",
-                    })
+            // The type annotation is optional; without one the type gets
+            // inferred
+            let mut type_name = None;
+            if let Token::Colon(_) = self.peek() {
+                self.pop();
+                match self.pop() {
+                    Token::Ident { id, view } => {
+                        type_name = Some(id);
+                        end = view.end;
+                    }
+                    x => {
+                        return Err(Error {
+                            location: x.view(),
+                            message: "unexpected token when parsing function arguments",
+                            code: Code::Syntax,
+                        })
+                    }
                 }
             }
 
@@ -348,7 +499,7 @@ where
         let (return_type, return_type_view) = match self.pop() {
             Token::Arrow(_) => {
                 let tok = self.pop();
-                if let Token::Ident { id, view } = tok {
+                if let Token::Ident { id, .. } = tok {
                     let tok = self.pop();
                     if let Token::Colon(_) = tok {
                         (Some(id), tok.view())
@@ -385,52 +536,199 @@ where
         return Ok(function);
     }
 
+    fn try_parse_class(&mut self) -> Result<Stmt<'b>, Error<'b>> {
+        match self.pop() {
+            Token::Class(_) => {}
+            _ => panic!(),
+        }
+
+        let (name, name_view) = match self.pop() {
+            Token::Ident { id, view } => (id, view),
+            x => return err(x.view(), "expected class name"),
+        };
+        self.expect_colon()?;
+        self.expect_newline()?;
+        match self.pop() {
+            Token::Indent { .. } => {}
+            x => return err(x.view(), "expected an indented class body"),
+        }
+
+        let mut fields = Vec::new();
+        let mut methods = Vec::new();
+        while !matches!(self.peek(), Token::Dedent(_) | Token::End(_)) {
+            let popped = self.popped;
+            if let Err(e) = self.try_parse_class_member(&mut fields, &mut methods) {
+                self.errors.push(Error {
+                    code: Code::Syntax,
+                    ..e
+                });
+                self.recover(popped);
+            }
+        }
+
+        match self.pop() {
+            Token::Dedent(_) => {}
+            x => return err(x.view(), "unexpected token when parsing class dedent"),
+        }
+
+        return Ok(Stmt::Class {
+            name,
+            name_view,
+            fields: self.buckets.add_array(fields),
+            methods: self.buckets.add_array(methods),
+        });
+    }
+
+    // Class bodies can only hold field declarations, methods and `pass`
+    fn try_parse_class_member(
+        &mut self,
+        fields: &mut Vec<ClassField<'b>>,
+        methods: &mut Vec<Stmt<'b>>,
+    ) -> Result<(), Error<'b>> {
+        let (name, name_view) = match self.peek() {
+            Token::Pass(_) => {
+                self.pop();
+                return self.expect_newline();
+            }
+            Token::Def(_) => {
+                methods.push(self.try_parse_func()?);
+                return Ok(());
+            }
+            Token::Ident { id, view } => (id, view),
+            x => return err(x.view(), "expected a field or method in class body"),
+        };
+
+        self.pop();
+        self.expect_colon()?;
+        let (type_name, type_view) = match self.pop() {
+            Token::Ident { id, view } => (id, view),
+            x => return err(x.view(), "type needs to be identifier"),
+        };
+
+        let mut default = None;
+        if let Token::Equal(_) = self.peek() {
+            self.pop();
+            let value = self.try_parse_expr()?;
+            default = Some(self.buckets.add(value));
+        }
+        self.expect_newline()?;
+
+        fields.push(ClassField {
+            name,
+            name_view,
+            type_name,
+            type_view,
+            default,
+        });
+        return Ok(());
+    }
+
     pub fn try_parse_expr(&mut self) -> Result<Expr<'b>, Error<'b>> {
-        return self.try_parse_expr_add();
+        return self.try_parse_expr_prec(OR_PREC);
     }
 
-    pub fn try_parse_expr_add(&mut self) -> Result<Expr<'b>, Error<'b>> {
-        use Token::*;
-        let mut expr = self.try_parse_unary_postfix()?;
+    // Precedence climbing; parses operators that bind at least as tightly as
+    // `min_prec`.
+    fn try_parse_expr_prec(&mut self, min_prec: u8) -> Result<Expr<'b>, Error<'b>> {
+        let mut expr = self.try_parse_expr_prefix()?;
         loop {
-            match self.peek() {
-                Dash(loc) => {
-                    self.pop();
-                    let left = self.buckets.add(expr);
-                    let right = self.try_parse_unary_postfix()?;
-                    let right = self.buckets.add(right);
-                    let view = joinr(left.view(), right.view());
-                    expr = Expr::Minus { left, right, view };
-                }
-                Plus(loc) => {
-                    self.pop();
-                    let left = self.buckets.add(expr);
-                    let right = self.try_parse_unary_postfix()?;
-                    let right = self.buckets.add(right);
-                    let view = joinr(left.view(), right.view());
-                    expr = Expr::Add { left, right, view };
+            let tok = self.peek();
+            if comparison_op(tok).is_some() {
+                if COMPARE_PREC < min_prec {
+                    return Ok(expr);
                 }
-                _ => return Ok(expr),
+
+                expr = self.try_parse_comparison(expr)?;
+                continue;
+            }
+
+            let (op, prec) = match binary_op(tok) {
+                Some(op) => op,
+                None => return Ok(expr),
+            };
+
+            if prec < min_prec {
+                return Ok(expr);
             }
+
+            self.pop();
+            let right = if op == BinOp::Pow {
+                // Right associative, and the exponent can have a sign, i.e.
+                // `2 ** -1`
+                self.try_parse_expr_prec(UNARY_PREC)?
+            } else {
+                self.try_parse_expr_prec(prec + 1)?
+            };
+
+            let left = self.buckets.add(expr);
+            let right = self.buckets.add(right);
+            let view = joinr(left.view(), right.view());
+            expr = Expr::Binary {
+                op,
+                left,
+                right,
+                view,
+            };
+        }
+    }
+
+    fn try_parse_expr_prefix(&mut self) -> Result<Expr<'b>, Error<'b>> {
+        use Token::*;
+        let (op, prec, begin) = match self.peek() {
+            Not(begin) => (UnaryOp::Not, NOT_PREC, begin),
+            Dash(begin) => (UnaryOp::Neg, UNARY_PREC, begin),
+            Plus(begin) => (UnaryOp::Pos, UNARY_PREC, begin),
+            _ => return self.try_parse_unary_postfix(),
+        };
+
+        self.pop();
+        let operand = self.try_parse_expr_prec(prec)?;
+        let operand = self.buckets.add(operand);
+        let view = newr(begin, operand.view().end);
+        return Ok(Expr::Unary { op, operand, view });
+    }
+
+    fn try_parse_comparison(&mut self, first: Expr<'b>) -> Result<Expr<'b>, Error<'b>> {
+        let mut rest = Vec::new();
+        while let Some(op) = comparison_op(self.peek()) {
+            self.pop();
+            let right = self.try_parse_expr_prec(COMPARE_PREC + 1)?;
+            rest.push(Comparison { op, right });
         }
+
+        let view = joinr(first.view(), rest.last().unwrap().right.view());
+        return Ok(Expr::Compare {
+            first: self.buckets.add(first),
+            rest: self.buckets.add_array(rest),
+            view,
+        });
     }
 
     pub fn try_parse_unary_postfix(&mut self) -> Result<Expr<'b>, Error<'b>> {
         let mut expr = self.try_parse_expr_atom()?;
 
         loop {
-            if let Token::Dot(begin) = self.peek() {
+            if let Token::Dot(_) = self.peek() {
                 self.pop();
                 match self.pop() {
                     Token::Ident { id, view } => {
                         let parent = self.buckets.add(expr);
-                        let start = parent.view().start;
-
-                        expr = Expr::DotAccess {
-                            parent,
-                            member_id: id,
-                            member_view: view,
-                        };
+                        if let Token::LParen(_) = self.peek() {
+                            let (arguments, arguments_view) = self.try_parse_arguments()?;
+                            expr = Expr::MethodCall {
+                                parent,
+                                method: id,
+                                method_view: view,
+                                arguments,
+                                arguments_view,
+                            };
+                        } else {
+                            expr = Expr::DotAccess {
+                                parent,
+                                member_id: id,
+                                member_view: view,
+                            };
+                        }
                     }
                     x => {
                         return err(x.view(), "expected identifier after dot");
@@ -447,30 +745,14 @@ where
         match self.peek() {
             Ident { id, view } => {
                 self.pop();
-                if let LParen(tup_begin) = self.peek() {
-                    let arguments = self.try_parse_expr_tup()?;
-                    let expr;
-                    if let Expr::Tup {
-                        values,
-                        view: eview,
-                    } = arguments
-                    {
-                        expr = Expr::Call {
-                            callee: id,
-                            callee_view: view,
-                            arguments: values,
-                            arguments_view: eview,
-                        };
-                    } else {
-                        let aview = arguments.view();
-                        expr = Expr::Call {
-                            callee: id,
-                            callee_view: view,
-                            arguments: self.buckets.add_array(vec![arguments]),
-                            arguments_view: aview,
-                        };
-                    }
-                    return Ok(expr);
+                if let LParen(_) = self.peek() {
+                    let (arguments, arguments_view) = self.try_parse_arguments()?;
+                    return Ok(Expr::Call {
+                        callee: id,
+                        callee_view: view,
+                        arguments,
+                        arguments_view,
+                    });
                 } else {
                     return Ok(Expr::Ident { id, view });
                 }
@@ -501,18 +783,17 @@ where
                 self.pop();
                 return Ok(Expr::True(t.view()));
             }
-            String { id, view } => {
+            String { view, .. } => {
                 self.pop();
-                panic!("not implemented yet");
-                // let substr = self.lexer.substr(view.start, view.end);
-                // let value = unwrap_err(unescape(substr), view, "invalid escape sequence")?;
-                // let value = self.buckets.add_str(&value);
-                // return Ok(Expr::StringLiteral { id, value, view });
+                let substr = self.lexer.substr(view.start, view.end);
+                let value = unwrap_err(unescape(substr), view, "invalid escape sequence")?;
+                let value = self.buckets.add_str(&value);
+                return Ok(Expr::StringLiteral { value, view });
             }
-            LParen(tup_begin) => {
+            LParen(_) => {
                 let mut tup = self.try_parse_expr_tup()?;
                 let slice = match &mut tup {
-                    Expr::Tup { values, view } => values,
+                    Expr::Tup { values, .. } => values,
                     _ => panic!(),
                 };
 
@@ -530,6 +811,14 @@ where
         }
     }
 
+    // The parenthesized arguments of a call, and where they are
+    fn try_parse_arguments(&mut self) -> Result<(&'b mut [Expr<'b>], CRange), Error<'b>> {
+        return match self.try_parse_expr_tup()? {
+            Expr::Tup { values, view } => Ok((values, view)),
+            _ => panic!(),
+        };
+    }
+
     pub fn try_parse_expr_tup(&mut self) -> Result<Expr<'b>, Error<'b>> {
         use Token::*;
         let tup_begin = match self.pop() {
@@ -537,34 +826,25 @@ where
             _ => panic!(),
         };
 
-        match self.peek() {
-            RParen(tup_end) => {
-                self.pop();
-                return Ok(Expr::Tup {
-                    values: self.buckets.add_array(Vec::new()),
-                    view: newr(tup_begin, tup_end + 1),
-                });
-            }
-            _ => {}
+        if let RParen(tup_end) = self.peek() {
+            self.pop();
+            return Ok(Expr::Tup {
+                values: self.buckets.add_array(Vec::new()),
+                view: newr(tup_begin, tup_end + 1),
+            });
         }
 
         let expr = self.try_parse_expr()?;
         let mut exprs = vec![expr];
         let mut tok = self.pop();
 
-        while match tok {
-            Comma(_) => true,
-            _ => false,
-        } {
-            if match self.peek() {
-                RParen(_) => true,
-                _ => false,
-            } {
+        while matches!(tok, Comma(_)) {
+            if matches!(self.peek(), RParen(_)) {
                 tok = self.pop();
                 break;
             }
 
-            exprs.push(self.try_parse_expr_add()?);
+            exprs.push(self.try_parse_expr()?);
             tok = self.pop();
         }
 
@@ -0,0 +1,662 @@
+use crate::assembler::Assembler;
+use crate::lexer::{Lexer, Token};
+use crate::runtime::Opcode;
+use crate::syntax_tree::*;
+use crate::util::line_col;
+use std::collections::HashMap;
+use std::io::{Result, Write};
+
+// Readable dumps of each stage of compilation, for `--emit`
+
+pub fn print_tokens(out: &mut dyn Write, input: &str) -> Result<()> {
+    let mut lexer = Lexer::new(input);
+    loop {
+        let token = lexer.next();
+        let view = token.view();
+        let (line, col) = line_col(input, view.start);
+        let location = format!("{}:{}", line, col);
+        match token {
+            Token::Newline(_) | Token::Indent { .. } | Token::Dedent(_) | Token::End(_) => {
+                writeln!(out, "{:<8} {}", location, token.name())?;
+            }
+            _ => {
+                let text = &input[view.into_range()];
+                writeln!(out, "{:<8} {:<12} {}", location, token.name(), text)?;
+            }
+        }
+
+        if let Token::End(_) = token {
+            return Ok(());
+        }
+    }
+}
+
+// The AST is printed back as source, with every compound expression in
+// parentheses so the structure the parser found is visible.
+pub fn print_ast(out: &mut dyn Write, names: &[String], stmts: &[Stmt]) -> Result<()> {
+    return print_stmts(out, names, stmts, 0);
+}
+
+fn print_stmts(out: &mut dyn Write, names: &[String], stmts: &[Stmt], depth: usize) -> Result<()> {
+    let indent = "    ".repeat(depth);
+    for stmt in stmts {
+        match stmt {
+            Stmt::Pass(_) => writeln!(out, "{}pass", indent)?,
+            Stmt::Break(_) => writeln!(out, "{}break", indent)?,
+            Stmt::Continue(_) => writeln!(out, "{}continue", indent)?,
+            Stmt::Expr(expr) => writeln!(out, "{}{}", indent, expr_str(names, expr))?,
+            Stmt::Declare {
+                name,
+                type_name,
+                value,
+                ..
+            } => {
+                let name = &names[*name as usize];
+                let type_name = &names[*type_name as usize];
+                let value = expr_str(names, value);
+                writeln!(out, "{}{}: {} = {}", indent, name, type_name, value)?;
+            }
+            Stmt::Assign { to, value, .. } => {
+                let value = expr_str(names, value);
+                writeln!(out, "{}{} = {}", indent, names[*to as usize], value)?;
+            }
+            Stmt::AssignMember {
+                to,
+                to_member,
+                value,
+                ..
+            } => {
+                let to = expr_str(names, to);
+                let member = &names[*to_member as usize];
+                let value = expr_str(names, value);
+                writeln!(out, "{}{}.{} = {}", indent, to, member, value)?;
+            }
+            Stmt::Return { ret_val } => {
+                writeln!(out, "{}return {}", indent, expr_str(names, ret_val))?;
+            }
+            Stmt::Function {
+                name,
+                arguments,
+                return_type,
+                stmts,
+                ..
+            } => {
+                let arguments: Vec<String> = arguments
+                    .iter()
+                    .map(|arg| match arg.type_name {
+                        Some(type_name) => format!(
+                            "{}: {}",
+                            names[arg.name as usize], names[type_name as usize]
+                        ),
+                        None => names[arg.name as usize].clone(),
+                    })
+                    .collect();
+                write!(
+                    out,
+                    "{}def {}({})",
+                    indent,
+                    names[*name as usize],
+                    arguments.join(", ")
+                )?;
+                if let Some(return_type) = return_type {
+                    write!(out, " -> {}", names[*return_type as usize])?;
+                }
+                writeln!(out, ":")?;
+                print_stmts(out, names, stmts, depth + 1)?;
+            }
+            Stmt::Class {
+                name,
+                fields,
+                methods,
+                ..
+            } => {
+                writeln!(out, "{}class {}:", indent, names[*name as usize])?;
+                let indent = "    ".repeat(depth + 1);
+                for field in fields.iter() {
+                    let name = &names[field.name as usize];
+                    let type_name = &names[field.type_name as usize];
+                    match &field.default {
+                        Some(value) => {
+                            let value = expr_str(names, value);
+                            writeln!(out, "{}{}: {} = {}", indent, name, type_name, value)?;
+                        }
+                        None => writeln!(out, "{}{}: {}", indent, name, type_name)?,
+                    }
+                }
+                if fields.is_empty() && methods.is_empty() {
+                    writeln!(out, "{}pass", indent)?;
+                }
+                print_stmts(out, names, methods, depth + 1)?;
+            }
+            Stmt::If {
+                conditioned_blocks,
+                else_branch,
+            } => {
+                for (idx, branch) in conditioned_blocks.iter().enumerate() {
+                    let keyword = if idx == 0 { "if" } else { "elif" };
+                    let condition = expr_str(names, &branch.condition);
+                    writeln!(out, "{}{} {}:", indent, keyword, condition)?;
+                    print_stmts(out, names, branch.block, depth + 1)?;
+                }
+                if !else_branch.is_empty() {
+                    writeln!(out, "{}else:", indent)?;
+                    print_stmts(out, names, else_branch, depth + 1)?;
+                }
+            }
+            Stmt::While {
+                condition,
+                block,
+                else_branch,
+            } => {
+                writeln!(out, "{}while {}:", indent, expr_str(names, condition))?;
+                print_stmts(out, names, block, depth + 1)?;
+                if !else_branch.is_empty() {
+                    writeln!(out, "{}else:", indent)?;
+                    print_stmts(out, names, else_branch, depth + 1)?;
+                }
+            }
+            Stmt::For {
+                target,
+                iterable,
+                block,
+                else_branch,
+                ..
+            } => {
+                let target = &names[*target as usize];
+                let iterable = expr_str(names, iterable);
+                writeln!(out, "{}for {} in {}:", indent, target, iterable)?;
+                print_stmts(out, names, block, depth + 1)?;
+                if !else_branch.is_empty() {
+                    writeln!(out, "{}else:", indent)?;
+                    print_stmts(out, names, else_branch, depth + 1)?;
+                }
+            }
+        }
+    }
+    return Ok(());
+}
+
+fn expr_str(names: &[String], expr: &Expr) -> String {
+    return match expr {
+        Expr::Int { value, .. } => value.to_string(),
+        Expr::Float { value, .. } => format!("{:?}", value),
+        Expr::StringLiteral { value, .. } => format!("{:?}", value),
+        Expr::None(_) => String::from("None"),
+        Expr::True(_) => String::from("True"),
+        Expr::False(_) => String::from("False"),
+        Expr::Ident { id, .. } => names[*id as usize].clone(),
+        Expr::Call {
+            callee, arguments, ..
+        } => {
+            let arguments: Vec<String> = arguments.iter().map(|a| expr_str(names, a)).collect();
+            format!("{}({})", names[*callee as usize], arguments.join(", "))
+        }
+        Expr::DotAccess {
+            parent, member_id, ..
+        } => format!("{}.{}", expr_str(names, parent), names[*member_id as usize]),
+        Expr::MethodCall {
+            parent,
+            method,
+            arguments,
+            ..
+        } => {
+            let arguments: Vec<String> = arguments.iter().map(|a| expr_str(names, a)).collect();
+            format!(
+                "{}.{}({})",
+                expr_str(names, parent),
+                names[*method as usize],
+                arguments.join(", ")
+            )
+        }
+        Expr::Tup { values, .. } => {
+            let values: Vec<String> = values.iter().map(|v| expr_str(names, v)).collect();
+            format!("({},)", values.join(", "))
+        }
+        Expr::Binary {
+            op, left, right, ..
+        } => format!(
+            "({} {} {})",
+            expr_str(names, left),
+            op.symbol(),
+            expr_str(names, right)
+        ),
+        Expr::Unary { op, operand, .. } => {
+            format!("({}{})", op.symbol(), expr_str(names, operand))
+        }
+        Expr::Compare { first, rest, .. } => {
+            let mut result = format!("({}", expr_str(names, first));
+            for comparison in rest.iter() {
+                let right = expr_str(names, &comparison.right);
+                result += &format!(" {} {}", comparison.op.symbol(), right);
+            }
+            result + ")"
+        }
+    };
+}
+
+// The typed tree is printed like the AST, except that every name and
+// compound expression is followed by its type, and functions list the locals
+// the assembler will allocate for them.
+pub fn print_typed(out: &mut dyn Write, names: &[String], program: &TProgram) -> Result<()> {
+    let mut functions = HashMap::new();
+    let mut classes = HashMap::new();
+    collect_functions(program.stmts, &mut functions, &mut classes);
+    let printer = TypedPrinter {
+        names,
+        functions,
+        classes,
+    };
+
+    writeln!(
+        out,
+        "globals: {}",
+        printer.declarations(program.declarations)
+    )?;
+    return printer.print_stmts(out, program.stmts, 0);
+}
+
+// Names of every function and class, by uid
+fn collect_functions(
+    stmts: &[TStmt],
+    functions: &mut HashMap<u32, u32>,
+    classes: &mut HashMap<u32, u32>,
+) {
+    for stmt in stmts {
+        match stmt {
+            TStmt::Function {
+                uid, name, stmts, ..
+            } => {
+                functions.insert(*uid, *name);
+                collect_functions(stmts, functions, classes);
+            }
+            TStmt::Class { uid, name, methods } => {
+                classes.insert(*uid, *name);
+                collect_functions(methods, functions, classes);
+            }
+            TStmt::If {
+                if_true, if_false, ..
+            } => {
+                collect_functions(if_true, functions, classes);
+                collect_functions(if_false, functions, classes);
+            }
+            TStmt::While {
+                block, else_block, ..
+            }
+            | TStmt::ForRange {
+                block, else_block, ..
+            } => {
+                collect_functions(block, functions, classes);
+                collect_functions(else_block, functions, classes);
+            }
+            _ => {}
+        }
+    }
+}
+
+struct TypedPrinter<'a> {
+    names: &'a [String],
+    functions: HashMap<u32, u32>,
+    classes: HashMap<u32, u32>,
+}
+
+impl<'a> TypedPrinter<'a> {
+    fn name(&self, id: u32) -> &str {
+        return &self.names[id as usize];
+    }
+
+    // Classes are printed by name, and interfaces by the names of their
+    // methods; the others print themselves
+    fn type_(&self, type_: Type) -> String {
+        return match type_ {
+            Type::Class(uid) => match self.classes.get(&uid) {
+                Some(&name) => String::from(self.name(name)),
+                None => type_.to_string(),
+            },
+            Type::Interface(methods) => {
+                let names: Vec<&str> = methods.iter().map(|m| self.name(m.name)).collect();
+                format!("interface({})", names.join(", "))
+            }
+            _ => type_.to_string(),
+        };
+    }
+
+    fn declarations(&self, declarations: &[Declaration]) -> String {
+        let mut names: Vec<&str> = declarations.iter().map(|d| self.name(d.name)).collect();
+        names.sort();
+        return names.join(", ");
+    }
+
+    fn print_stmts(&self, out: &mut dyn Write, stmts: &[TStmt], depth: usize) -> Result<()> {
+        let indent = "    ".repeat(depth);
+        for stmt in stmts {
+            match stmt {
+                TStmt::Expr(expr) => writeln!(out, "{}{}", indent, self.expr(expr))?,
+                TStmt::Assign { to, value } => {
+                    writeln!(out, "{}{} = {}", indent, self.name(*to), self.expr(value))?;
+                }
+                TStmt::AssignMember {
+                    object,
+                    name,
+                    value,
+                    ..
+                } => {
+                    let object = self.expr(object);
+                    let value = self.expr(value);
+                    writeln!(out, "{}{}.{} = {}", indent, object, self.name(*name), value)?;
+                }
+                TStmt::Class { uid, name, methods } => {
+                    writeln!(out, "{}class {}:  # uid {}", indent, self.name(*name), uid)?;
+                    self.print_stmts(out, methods, depth + 1)?;
+                }
+                TStmt::Return { ret_val } => {
+                    writeln!(out, "{}return {}", indent, self.expr(ret_val))?;
+                }
+                TStmt::Break => writeln!(out, "{}break", indent)?,
+                TStmt::Continue => writeln!(out, "{}continue", indent)?,
+                TStmt::Trap { error } => writeln!(out, "{}trap {}", indent, error)?,
+                TStmt::Function {
+                    uid,
+                    name,
+                    argument_names,
+                    declarations,
+                    stmts,
+                } => {
+                    let arguments: Vec<&str> =
+                        argument_names.iter().map(|&arg| self.name(arg)).collect();
+                    write!(
+                        out,
+                        "{}def {}({}):  # uid {}",
+                        indent,
+                        self.name(*name),
+                        arguments.join(", "),
+                        uid
+                    )?;
+                    if !declarations.is_empty() {
+                        write!(out, ", locals: {}", self.declarations(declarations))?;
+                    }
+                    writeln!(out)?;
+                    self.print_stmts(out, stmts, depth + 1)?;
+                }
+                TStmt::If {
+                    condition,
+                    if_true,
+                    if_false,
+                } => {
+                    writeln!(out, "{}if {}:", indent, self.expr(condition))?;
+                    self.print_stmts(out, if_true, depth + 1)?;
+                    if !if_false.is_empty() {
+                        writeln!(out, "{}else:", indent)?;
+                        self.print_stmts(out, if_false, depth + 1)?;
+                    }
+                }
+                TStmt::While {
+                    condition,
+                    block,
+                    else_block,
+                } => {
+                    writeln!(out, "{}while {}:", indent, self.expr(condition))?;
+                    self.print_stmts(out, block, depth + 1)?;
+                    if !else_block.is_empty() {
+                        writeln!(out, "{}else:", indent)?;
+                        self.print_stmts(out, else_block, depth + 1)?;
+                    }
+                }
+                TStmt::ForRange {
+                    var,
+                    start,
+                    stop,
+                    step,
+                    block,
+                    else_block,
+                    ..
+                } => {
+                    writeln!(
+                        out,
+                        "{}for {} in range({}, {}, {}):",
+                        indent,
+                        self.name(*var),
+                        self.expr(start),
+                        self.expr(stop),
+                        self.expr(step)
+                    )?;
+                    self.print_stmts(out, block, depth + 1)?;
+                    if !else_block.is_empty() {
+                        writeln!(out, "{}else:", indent)?;
+                        self.print_stmts(out, else_block, depth + 1)?;
+                    }
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    fn expr(&self, expr: &TExpr) -> String {
+        return match expr {
+            TExpr::None => String::from("None"),
+            TExpr::Int(value) => value.to_string(),
+            TExpr::Float(value) => format!("{:?}", value),
+            TExpr::Bool(true) => String::from("True"),
+            TExpr::Bool(false) => String::from("False"),
+            TExpr::Str(value) => format!("{:?}", value),
+            TExpr::Ident { id, type_ } => format!("({}: {})", self.name(*id), self.type_(*type_)),
+            TExpr::Binary {
+                op,
+                left,
+                right,
+                type_,
+                ..
+            } => format!(
+                "({} {} {}): {}",
+                self.expr(left),
+                op.symbol(),
+                self.expr(right),
+                self.type_(*type_)
+            ),
+            TExpr::Unary {
+                op, operand, type_, ..
+            } => format!(
+                "({}{}): {}",
+                op.symbol(),
+                self.expr(operand),
+                self.type_(*type_)
+            ),
+            TExpr::Compare { first, rest, .. } => {
+                let mut result = format!("({}", self.expr(first));
+                for comparison in rest.iter() {
+                    let right = self.expr(&comparison.right);
+                    result += &format!(" {} {}", comparison.op.symbol(), right);
+                }
+                result + "): bool"
+            }
+            TExpr::Call {
+                callee_uid,
+                arguments,
+                type_,
+                ..
+            } => {
+                let arguments: Vec<String> = arguments.iter().map(|a| self.expr(a)).collect();
+                let callee = match self.functions.get(callee_uid) {
+                    Some(&name) => self.name(name),
+                    None => "?",
+                };
+                format!(
+                    "{}({}): {}",
+                    callee,
+                    arguments.join(", "),
+                    self.type_(*type_)
+                )
+            }
+            TExpr::New {
+                class,
+                name,
+                arguments,
+                ..
+            } => {
+                let arguments: Vec<String> = arguments.iter().map(|a| self.expr(a)).collect();
+                let type_ = self.type_(Type::Class(*class));
+                format!("{}({}): {}", self.name(*name), arguments.join(", "), type_)
+            }
+            TExpr::Member {
+                object,
+                name,
+                type_,
+                ..
+            } => format!(
+                "{}.{}: {}",
+                self.expr(object),
+                self.name(*name),
+                self.type_(*type_)
+            ),
+            TExpr::MethodCall {
+                object,
+                name,
+                arguments,
+                type_,
+                ..
+            } => {
+                let arguments: Vec<String> = arguments.iter().map(|a| self.expr(a)).collect();
+                format!(
+                    "{}.{}({}): {}",
+                    self.expr(object),
+                    self.name(*name),
+                    arguments.join(", "),
+                    self.type_(*type_)
+                )
+            }
+            TExpr::Upcast { value, interface } => format!(
+                "{} as {}",
+                self.expr(value),
+                self.type_(Type::Interface(interface))
+            ),
+            TExpr::InterfaceCall {
+                object,
+                interface,
+                method,
+                arguments,
+                ..
+            } => {
+                let method = interface[*method as usize];
+                let arguments: Vec<String> = arguments.iter().map(|a| self.expr(a)).collect();
+                format!(
+                    "{}.{}({}): {}",
+                    self.expr(object),
+                    self.name(method.name),
+                    arguments.join(", "),
+                    self.type_(*method.return_type)
+                )
+            }
+            TExpr::ECall { arguments, type_ } => {
+                let arguments: Vec<String> = arguments.iter().map(|a| self.expr(a)).collect();
+                format!("ecall({}): {}", arguments.join(", "), self.type_(*type_))
+            }
+        };
+    }
+}
+
+// One op per line with its address, and a label wherever a function starts
+pub fn print_bytecode(
+    out: &mut dyn Write,
+    names: &[String],
+    assembler: &Assembler,
+    ops: &[Opcode],
+) -> Result<()> {
+    for (address, op) in ops.iter().enumerate() {
+        let address = address as u32;
+        if assembler.is_function_start(address) {
+            let name = match assembler.function_name_at(address) {
+                Some(name) => names[name as usize].as_str(),
+                None => "<module>",
+            };
+            writeln!(out, "{}:", name)?;
+        }
+
+        let target = match *op {
+            Opcode::Call(address)
+            | Opcode::FunctionAddress(address)
+            | Opcode::ItableEntry(address) => assembler.function_name_at(address),
+            _ => None,
+        };
+        match target {
+            Some(name) => writeln!(
+                out,
+                "{:>6}  {}  # {}",
+                address,
+                op_str(op),
+                names[name as usize]
+            )?,
+            None => writeln!(out, "{:>6}  {}", address, op_str(op))?,
+        }
+    }
+    return Ok(());
+}
+
+fn op_str(op: &Opcode) -> String {
+    use Opcode::*;
+    return match *op {
+        BeginStringData(len) => format!("begin_string_data {}", len),
+        StringData(word) => format!("string_data {:#018x}", word),
+        BeginItable {
+            type_index,
+            interface,
+        } => format!("begin_itable type={} interface={}", type_index, interface),
+        ItableEntry(address) => format!("itable_entry {}", address),
+        MakeInt(value) => format!("make_int {}", value),
+        MakeFloat(value) => format!("make_float {:?}", value),
+        MakeBool(value) => format!("make_bool {}", value),
+        MakeStr(id) => format!("make_str {}", id),
+        GetGlobal { stack_offset } => format!("get_global {}", stack_offset),
+        SetGlobal { stack_offset } => format!("set_global {}", stack_offset),
+        GetLocal { stack_offset } => format!("get_local {}", stack_offset),
+        SetLocal { stack_offset } => format!("set_local {}", stack_offset),
+        HeapRead { offset } => format!("heap_read {}", offset),
+        HeapWrite { offset } => format!("heap_write {}", offset),
+        HeapAlloc { header } => format!(
+            "heap_alloc type={} size={}",
+            header.type_index, header.object_size
+        ),
+        Call(address) => format!("call {}", address),
+        FunctionAddress(address) => format!("function_address {}", address),
+        Pick(depth) => format!("pick {}", depth),
+        InterfaceMethod { interface, method } => {
+            format!("interface_method interface={} method={}", interface, method)
+        }
+        JumpIf(address) => format!("jump_if {}", address),
+        JumpNotIf(address) => format!("jump_not_if {}", address),
+        Jump(address) => format!("jump {}", address),
+        ForRange(address) => format!("for_range {}", address),
+        Trap(error) => format!("trap {}", error),
+        AddFloat => String::from("add_float"),
+        AddInt => String::from("add_int"),
+        SubFloat => String::from("sub_float"),
+        SubInt => String::from("sub_int"),
+        MulFloat => String::from("mul_float"),
+        MulInt => String::from("mul_int"),
+        DivFloat => String::from("div_float"),
+        DivInt => String::from("div_int"),
+        FloorDivFloat => String::from("floor_div_float"),
+        FloorDivInt => String::from("floor_div_int"),
+        ModFloat => String::from("mod_float"),
+        ModInt => String::from("mod_int"),
+        PowFloat => String::from("pow_float"),
+        PowInt => String::from("pow_int"),
+        NegFloat => String::from("neg_float"),
+        NegInt => String::from("neg_int"),
+        Concat => String::from("concat"),
+        Not => String::from("not"),
+        Equal => String::from("equal"),
+        NotEqual => String::from("not_equal"),
+        Less => String::from("less"),
+        LessEqual => String::from("less_equal"),
+        Greater => String::from("greater"),
+        GreaterEqual => String::from("greater_equal"),
+        PushNone => String::from("push_none"),
+        Pop => String::from("pop"),
+        Dup => String::from("dup"),
+        Swap => String::from("swap"),
+        Rot => String::from("rot"),
+        Return => String::from("return"),
+        CallDyn => String::from("call_dyn"),
+        ECall => String::from("ecall"),
+        Halt => String::from("halt"),
+    };
+}
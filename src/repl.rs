@@ -0,0 +1,277 @@
+use crate::assembler::Assembler;
+use crate::builtins::*;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::runtime::*;
+use crate::syntax_tree::*;
+use crate::type_checker::*;
+use crate::util::*;
+use crate::{error_diagnostics, fault_location, traceback};
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+use codespan_reporting::files::SimpleFiles;
+use std::io::{BufRead, Write};
+
+pub type Files<'b> = SimpleFiles<&'static str, &'b str>;
+
+// An interactive session. Every entry is checked against the globals declared
+// by the entries before it, assembled onto the end of the session's code and
+// run on the same runtime, so globals keep their values between entries.
+pub struct Repl<'b, Out>
+where
+    Out: Write,
+{
+    pub files: Files<'b>,
+    buckets: Buckets<'b>,
+    names: Vec<String>,
+    symbols: SymbolTable<'b>,
+    next_uid: u32,
+    assembler: Assembler,
+    code: Vec<Opcode>,
+    runtime: Runtime<Out>,
+    // The errors of every entry that was run, silenced ones included, with
+    // the file they're in, since traps can be reached from later entries
+    errors: Vec<(usize, Error<'b>)>,
+}
+
+impl<'b, Out> Repl<'b, Out>
+where
+    Out: Write,
+{
+    pub fn new(stdout: Out) -> Self {
+        return Self::with_host(stdout, &Host::new());
+    }
+
+    pub fn with_host(stdout: Out, host: &Host) -> Self {
+        let mut buckets = Buckets::new();
+        let symbols = builtin_symbols(&mut buckets, host);
+        let (names, _) = builtin_names(host);
+        let mut runtime = Runtime::new(stdout);
+        runtime.install(host);
+
+        let mut repl = Self {
+            files: SimpleFiles::new(),
+            names: names.into_iter().map(String::from).collect(),
+            symbols: SymbolTable::new_global(symbols),
+            next_uid: host.first_free_uid(),
+            assembler: Assembler::new(),
+            code: Vec::new(),
+            runtime,
+            buckets,
+            errors: Vec::new(),
+        };
+
+        // The builtins go in first, as an entry of their own
+        let declarations = repl
+            .symbols
+            .symbols
+            .keys()
+            .map(|&name| Declaration { name })
+            .collect();
+        let stmts = builtin_definitions(&mut repl.buckets, host);
+        let program = TProgram {
+            declarations: repl.buckets.add_array(declarations),
+            stmts: repl.buckets.add_array(stmts),
+        };
+        repl.run_entry(program)
+            .expect("builtin definitions shouldn't fail");
+
+        return repl;
+    }
+
+    // Reads entries from `input` until it runs out, passing along anything
+    // that goes wrong. With `prompt` set, prompts are written to the same
+    // output the program prints to.
+    pub fn run(
+        &mut self,
+        mut input: impl BufRead,
+        prompt: bool,
+        report: &mut dyn FnMut(&Files<'b>, &Diagnostic<usize>),
+    ) {
+        loop {
+            let entry = match self.read_entry(&mut input, prompt) {
+                Some(entry) => entry,
+                None => return,
+            };
+
+            for diagnostic in self.eval(&entry) {
+                report(&self.files, &diagnostic);
+            }
+        }
+    }
+
+    // A line ending in a colon starts a block, which continues until the
+    // next empty line
+    fn read_entry(&mut self, input: &mut impl BufRead, prompt: bool) -> Option<String> {
+        let mut entry = String::new();
+        loop {
+            if prompt {
+                let prompt = if entry.is_empty() { ">>> " } else { "... " };
+                write!(self.runtime.stdout, "{}", prompt).expect("why did this fail?");
+                self.runtime.stdout.flush().expect("why did this fail?");
+            }
+
+            let mut line = String::new();
+            if input.read_line(&mut line).expect("failed to read input") == 0 {
+                if entry.is_empty() {
+                    return None;
+                }
+                return Some(entry);
+            }
+
+            let line = line.trim_end();
+            if entry.is_empty() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+
+                entry += line;
+                entry += "\n";
+                if !line.ends_with(':') {
+                    return Some(entry);
+                }
+            } else if line.trim().is_empty() {
+                return Some(entry);
+            } else {
+                entry += line;
+                entry += "\n";
+            }
+        }
+    }
+
+    // Runs one entry. If it doesn't compile, nothing in it runs and the
+    // session is left as it was.
+    pub fn eval(&mut self, source: &str) -> Vec<Diagnostic<usize>> {
+        let source: &'b str = self.buckets.add_str(source);
+        let file_id = self.files.add("<stdin>", source);
+
+        let id_list = self.names.iter().map(String::as_str).collect();
+        let mut parser = Parser::with_lexer(&mut self.buckets, Lexer::with_names(source, id_list));
+        let parse_result = parser.try_parse_program();
+        let silenced = parser.lexer.take_silenced();
+        let new_names = &parser.lexer.id_list[self.names.len()..];
+        let new_names: Vec<String> = new_names.iter().map(|&name| name.into()).collect();
+        self.names.extend(new_names);
+
+        let program = match parse_result {
+            Ok(p) => self.buckets.add_array(p),
+            Err(errors) => return error_diagnostics(file_id, &errors),
+        };
+
+        let snapshot = self.symbols.symbols.clone();
+        let mut checker = TypeChecker::new(&mut self.buckets);
+        checker.set_next_uid(self.next_uid);
+        checker.set_first_error(self.errors.len() as u32);
+        checker.set_silenced(silenced);
+        let (program, errors) = checker.check_entry(&mut self.symbols, program);
+        let mut diagnostics = error_diagnostics(file_id, &checker.take_warnings());
+        let silenced = checker.take_silenced();
+        self.next_uid = checker.next_free_uid();
+
+        // Silenced errors don't stop the entry from running, but still trap
+        // if they're reached
+        let reported: Vec<Error> = errors
+            .iter()
+            .filter(|e| !silenced.contains(e.code, e.location))
+            .copied()
+            .collect();
+        if !reported.is_empty() {
+            self.symbols.symbols = snapshot;
+            diagnostics.extend(error_diagnostics(file_id, &reported));
+            return diagnostics;
+        }
+        self.errors.extend(errors.into_iter().map(|e| (file_id, e)));
+
+        let program = self.echo_results(program);
+        match self.run_entry(program) {
+            Ok(()) => return diagnostics,
+            Err(RuntimeError::Trap(error)) => {
+                let (error_file, error) = self.errors[error as usize];
+                let error_source = self.files.get(error_file).unwrap().source();
+                let traceback = traceback(
+                    &self.assembler,
+                    &self.runtime,
+                    &self.names,
+                    error_source,
+                    Some(error.location),
+                );
+                self.runtime.unwind();
+                diagnostics.push(
+                    Diagnostic::error()
+                        .with_code(error.code.name())
+                        .with_message("execution reached code that failed to compile")
+                        .with_labels(vec![Label::primary(
+                            error_file,
+                            error.location.into_range(),
+                        )
+                        .with_message(error.message)])
+                        .with_notes(vec![traceback]),
+                );
+                return diagnostics;
+            }
+            Err(RuntimeError::Fault { pc, message }) => {
+                let mut diagnostic = Diagnostic::error()
+                    .with_code(Code::Runtime.name())
+                    .with_message(message);
+                if let Some(location) = fault_location(&self.assembler, &self.runtime, pc) {
+                    diagnostic = diagnostic
+                        .with_labels(vec![Label::primary(file_id, location.into_range())]);
+                }
+
+                let location = self.assembler.source_map.get(&(pc as u32)).copied();
+                let traceback = traceback(
+                    &self.assembler,
+                    &self.runtime,
+                    &self.names,
+                    source,
+                    location,
+                );
+                self.runtime.unwind();
+                diagnostics.push(diagnostic.with_notes(vec![traceback]));
+                return diagnostics;
+            }
+        }
+    }
+
+    // Top-level expressions that produce a value get printed
+    fn echo_results(&mut self, program: TProgram<'b>) -> TProgram<'b> {
+        let mut stmts = Vec::new();
+        for stmt in program.stmts {
+            match stmt {
+                TStmt::Expr(expr) if expr.type_() != Type::None => {
+                    let arguments = vec![TExpr::Int(PRINT_PRIMITIVE as i64), **expr];
+                    let arguments = self.buckets.add_array(arguments);
+                    let echo = self.buckets.add(TExpr::ECall {
+                        arguments,
+                        type_: Type::None,
+                    });
+                    stmts.push(TStmt::Expr(echo));
+                }
+                stmt => stmts.push(*stmt),
+            }
+        }
+
+        return TProgram {
+            declarations: program.declarations,
+            stmts: self.buckets.add_array(stmts),
+        };
+    }
+
+    fn run_entry(&mut self, program: TProgram<'b>) -> Result<(), RuntimeError> {
+        let base = self.code.len();
+        let mut ops = self.assembler.assemble_entry(program, base as u32);
+        self.code.append(&mut ops);
+
+        self.runtime
+            .resize_globals(self.assembler.global_frame_size());
+        return self.runtime.run_from(&self.code, base);
+    }
+}
+
+impl<'b, Out> Drop for Repl<'b, Out>
+where
+    Out: Write,
+{
+    fn drop(&mut self) {
+        self.buckets.drop();
+    }
+}
@@ -1,3 +1,7 @@
+use crate::builtins::{Host, HostCall, HOST_ECALL_BEGIN};
+use crate::syntax_tree::Type;
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::io::Write;
 use std::slice;
 
@@ -20,17 +24,46 @@ pub enum Opcode {
     // Data segment opcodes
     BeginStringData(u64), // take the next many bytes of string data
     StringData(u64),
+    // The addresses of a class's methods for an interface, in the
+    // interface's order, as the ItableEntry ops after it
+    BeginItable { type_index: u32, interface: u32 },
+    ItableEntry(u32), // absolute address
 
     // Text segment opcodes
     MakeInt(i64),
     MakeFloat(f64),
     MakeBool(bool),
+    MakeStr(u32), // index into the data segment's strings
     AddFloat,
     AddInt,
     SubFloat,
     SubInt,
+    MulFloat,
+    MulInt,
+    DivFloat,
+    DivInt, // true division, so the result is a float
+    FloorDivFloat,
+    FloorDivInt,
+    ModFloat,
+    ModInt,
+    PowFloat,
+    PowInt,
+    NegFloat,
+    NegInt,
+    Concat,
+    Not,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
     PushNone,
     Pop,
+    Dup,
+    Swap,
+    Rot,       // moves the top of the stack below the two values under it
+    Pick(u32), // copies the value this many slots below the top onto the top
     GetGlobal { stack_offset: u32 },
     SetGlobal { stack_offset: u32 },
     GetLocal { stack_offset: i32 },
@@ -39,49 +72,183 @@ pub enum Opcode {
     HeapWrite { offset: u32 },
     HeapAlloc { header: ObjectHeader },
     Return,
-    Call(u32),      // absolute address
-    CallDyn,        // Absolute address
+    Call(u32), // absolute address
+    CallDyn,   // Absolute address
+    // Pushes an absolute address as an int, for CallDyn
+    FunctionAddress(u32),
+    // Replaces the object on top of the stack with the address of its class's
+    // implementation of the interface's method, for CallDyn
+    InterfaceMethod { interface: u32, method: u32 },
     JumpIf(u32),    // absolute address
     JumpNotIf(u32), // absolute address
     Jump(u32),      // absolute address
+    // The stack holds a range loop's counter, stop and step. Pushes the
+    // counter and advances it, or jumps to the address once it's done.
+    ForRange(u32),
     ECall,
+    Halt,      // stops execution without unwinding the stack
+    Trap(u32), // index of the compile error for the statement that was here
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuntimeError {
+    // Execution reached a statement that failed to type check
+    Trap(u32),
+    // The program did something invalid at runtime; `pc` is the address of
+    // the op that failed.
+    Fault { pc: usize, message: &'static str },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct HeapStats {
+    pub collections: usize,
+    pub heap_words: usize,
+    pub peak_heap_words: usize,
+    pub live_words: usize, // as of the last collection
+    pub freed_words: usize,
+    pub freed_objects: usize,
+}
+
+// A value passed to or returned from a host function
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    None,
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Str(String),
+}
+
+impl Value {
+    // None is a value of every type, like everywhere else
+    pub fn is_a(&self, type_: Type) -> bool {
+        return matches!(
+            (self, type_),
+            (Value::None, _)
+                | (Value::Int(_), Type::Int)
+                | (Value::Float(_), Type::Float)
+                | (Value::Bool(_), Type::Bool)
+                | (Value::Str(_), Type::Str)
+        );
+    }
 }
 
 pub struct Runtime<Out>
 where
     Out: Write,
 {
-    pub stack: Vec<usize>,
+    pub stack: Vec<u64>,
     pub heap: Vec<u64>,
+    pub strings: Vec<usize>,
+    // Method addresses by instance type index and interface
+    pub itables: HashMap<(u32, u32), Vec<u32>>,
     pub fp_ra_stack: Vec<usize>,
     pub stdout: Out,
     pub fp: usize,
     pub pc: usize,
+    pub next_collection: usize,
+    pub stats: HeapStats,
+    // Arity, return type and implementation of every host function, by ecall
+    host_calls: Vec<(usize, Type<'static>, HostCall)>,
 }
 
-pub const NONE_VALUE: usize = !0;
+// Values are NaN-boxed. Any float is stored as itself, with every NaN
+// collapsed into QNAN. The rest of the quiet NaN space holds everything else:
+// with the sign bit set, the low 51 bits are a signed int; without it, bits
+// 48-50 are a tag and the low 48 bits are the payload.
+const QNAN: u64 = 0x7ff8_0000_0000_0000;
+const SIGN_BIT: u64 = 1 << 63;
+const INT_BITS: u64 = SIGN_BIT | QNAN;
+const INT_PAYLOAD: u64 = (1 << 51) - 1;
+const TAG_MASK: u64 = SIGN_BIT | QNAN | (7 << 48);
+const PAYLOAD: u64 = (1 << 48) - 1;
+const PTR_TAG: u64 = QNAN | (1 << 48);
+const BOOL_TAG: u64 = QNAN | (2 << 48);
+pub const NONE_VALUE: u64 = QNAN | (3 << 48);
+
+// Returning to this address ends the program
+const EXIT_ADDRESS: usize = !0;
+
+pub const INT_MIN: i64 = -(1 << 50);
+pub const INT_MAX: i64 = (1 << 50) - 1;
 
-const INT_HEADER: ObjectHeader = ObjectHeader {
-    type_index: 0,
-    object_size: 1,
-};
-const FLOAT_HEADER: ObjectHeader = ObjectHeader {
-    type_index: 1,
-    object_size: 1,
-};
-const BOOL_HEADER: ObjectHeader = ObjectHeader {
-    type_index: 2,
-    object_size: 1,
-};
 const STRING_TYPE_INDEX: u32 = 3;
 pub const STACK_FRAME_TYPE_INDEX: u32 = 4;
 pub const FUNCTION_HEADER: ObjectHeader = ObjectHeader {
     type_index: 5,
     object_size: 2,
 };
+// Holds the frame a class was declared in, followed by the addresses of its
+// methods
+pub const CLASS_TYPE_INDEX: u32 = 6;
+// Every class gets its own type index for its instances, starting here.
+// Instances hold their class, followed by their fields.
+pub const FIRST_INSTANCE_TYPE_INDEX: u32 = 16;
+
+// The heap is collected when it grows past this many words, or past twice the
+// size of whatever survived the last collection if that's bigger.
+pub const MIN_COLLECTION_THRESHOLD: usize = 1 << 16;
 
 pub const PRINT_PRIMITIVE: u64 = 0;
 pub const FLOAT_CAST: u64 = 1;
+pub const INT_CAST: u64 = 2;
+pub const BOOL_CAST: u64 = 3;
+pub const STR_CAST: u64 = 4;
+
+pub fn is_int(value: u64) -> bool {
+    return value & INT_BITS == INT_BITS;
+}
+
+pub fn is_float(value: u64) -> bool {
+    return value & QNAN != QNAN || value == QNAN;
+}
+
+pub fn is_bool(value: u64) -> bool {
+    return value & TAG_MASK == BOOL_TAG;
+}
+
+pub fn is_ptr(value: u64) -> bool {
+    return value & TAG_MASK == PTR_TAG;
+}
+
+pub fn int_value(value: i64) -> Option<u64> {
+    if !(INT_MIN..=INT_MAX).contains(&value) {
+        return None;
+    }
+    return Some(INT_BITS | (value as u64 & INT_PAYLOAD));
+}
+
+pub fn float_value(value: f64) -> u64 {
+    if value.is_nan() {
+        return QNAN;
+    }
+    return value.to_bits();
+}
+
+pub fn bool_value(value: bool) -> u64 {
+    return BOOL_TAG | value as u64;
+}
+
+pub fn ptr_value(idx: usize) -> u64 {
+    return PTR_TAG | idx as u64;
+}
+
+pub fn as_int(value: u64) -> i64 {
+    // Shift the payload's sign bit into the top bit and back to sign-extend
+    return ((value << 13) as i64) >> 13;
+}
+
+pub fn as_float(value: u64) -> f64 {
+    return f64::from_bits(value);
+}
+
+pub fn as_bool(value: u64) -> bool {
+    return value & 1 != 0;
+}
+
+pub fn as_ptr(value: u64) -> usize {
+    return (value & PAYLOAD) as usize;
+}
 
 impl<Out> Runtime<Out>
 where
@@ -91,16 +258,227 @@ where
         return Self {
             stack: Vec::new(), // dummy frame pointer value
             heap: Vec::new(),
-            fp_ra_stack: vec![NONE_VALUE, 0],
+            strings: Vec::new(),
+            itables: HashMap::new(),
+            fp_ra_stack: vec![EXIT_ADDRESS, 0],
             stdout,
             fp: 0,
             pc: 0,
+            next_collection: MIN_COLLECTION_THRESHOLD,
+            stats: HeapStats::default(),
+            host_calls: Vec::new(),
         };
     }
 
-    pub fn run(&mut self, code: &Vec<Opcode>) {
-        while self.pc != NONE_VALUE {
-            self.run_op(code[self.pc]);
+    // Makes the host's functions callable by programs compiled against it
+    pub fn install(&mut self, host: &Host) {
+        self.host_calls = host
+            .functions
+            .iter()
+            .map(|f| (f.arguments.len(), f.return_type, f.call.clone()))
+            .collect();
+    }
+
+    pub fn run(&mut self, code: &[Opcode]) -> Result<(), RuntimeError> {
+        return self.run_from(code, 0);
+    }
+
+    pub fn run_from(&mut self, code: &[Opcode], start: usize) -> Result<(), RuntimeError> {
+        self.pc = start;
+        self.load_data(code);
+        while self.pc != EXIT_ADDRESS {
+            // Between instructions every live value is reachable from the
+            // stack, so this is the only place collection is safe.
+            if self.heap.len() >= self.next_collection {
+                self.collect();
+            }
+            self.run_op(code[self.pc])?;
+        }
+        self.update_peak();
+        return Ok(());
+    }
+
+    // Makes the global frame at the bottom of the stack `size` slots big,
+    // allocating it if it doesn't exist yet. Only valid between runs, when
+    // the global frame is the only thing on the stack and the only frame
+    // anything can refer to.
+    pub fn resize_globals(&mut self, size: u32) {
+        let header = ObjectHeader {
+            type_index: STACK_FRAME_TYPE_INDEX,
+            object_size: size,
+        };
+
+        let old = match self.stack.first() {
+            Some(&old) => old,
+            None => {
+                self.run_op(Opcode::HeapAlloc { header }).unwrap();
+                return;
+            }
+        };
+
+        let old_header = self.get_obj_header(as_ptr(old));
+        if old_header.object_size >= size {
+            return;
+        }
+
+        self.heap.push(header.to_bits());
+        let new = ptr_value(self.heap.len());
+        for idx in 0..size as usize {
+            let mut value = NONE_VALUE;
+            if idx < old_header.object_size as usize {
+                value = self.heap[as_ptr(old) + idx];
+            }
+
+            // Functions hold on to the frame they were defined in
+            self.heap.push(if value == old { new } else { value });
+        }
+        self.stack[0] = new;
+
+        // So do classes, for their methods
+        let mut scan = 0;
+        while scan < self.heap.len() {
+            let ptr = scan + 1;
+            let header = self.get_obj_header(ptr);
+            if header.type_index == CLASS_TYPE_INDEX && self.heap[ptr] == old {
+                self.heap[ptr] = new;
+            }
+            scan += object_words(header) + 1;
+        }
+    }
+
+    // Drops whatever a failed run left behind, except for the global frame
+    pub fn unwind(&mut self) {
+        self.stack.truncate(1);
+        self.fp_ra_stack.truncate(2);
+        self.fp = 0;
+    }
+
+    pub fn heap_stats(&self) -> HeapStats {
+        let mut stats = self.stats;
+        stats.heap_words = self.heap.len();
+        stats.peak_heap_words = stats.peak_heap_words.max(self.heap.len());
+        return stats;
+    }
+
+    fn update_peak(&mut self) {
+        self.stats.peak_heap_words = self.stats.peak_heap_words.max(self.heap.len());
+    }
+
+    // Mark-compact collection. The roots are the value stack and the string
+    // table; `fp_ra_stack` only holds return addresses and stack indices, so
+    // there's nothing in it to trace or relocate. Live objects keep their
+    // order and slide down to the start of the heap.
+    pub fn collect(&mut self) {
+        self.update_peak();
+
+        let mut marked = vec![false; self.heap.len() + 1];
+        let mut worklist: Vec<usize> = self.strings.clone();
+        worklist.extend(
+            self.stack
+                .iter()
+                .filter(|&&v| is_ptr(v))
+                .map(|&v| as_ptr(v)),
+        );
+        while let Some(ptr) = worklist.pop() {
+            if marked[ptr] {
+                continue;
+            }
+
+            marked[ptr] = true;
+            let header = self.get_obj_header(ptr);
+            if has_pointer_fields(header) {
+                let fields = &self.heap[ptr..(ptr + header.object_size as usize)];
+                let pointers = fields.iter().filter(|&&v| is_ptr(v));
+                worklist.extend(pointers.map(|&v| as_ptr(v)));
+            }
+        }
+
+        let mut forward = vec![0; self.heap.len() + 1];
+        let mut free = 0;
+        let mut scan = 0;
+        while scan < self.heap.len() {
+            let ptr = scan + 1;
+            let size = object_words(self.get_obj_header(ptr)) + 1;
+            if marked[ptr] {
+                forward[ptr] = free + 1;
+                free += size;
+            } else {
+                self.stats.freed_objects += 1;
+            }
+            scan += size;
+        }
+
+        for string in &mut self.strings {
+            *string = forward[*string];
+        }
+        for value in &mut self.stack {
+            if is_ptr(*value) {
+                *value = ptr_value(forward[as_ptr(*value)]);
+            }
+        }
+
+        let mut scan = 0;
+        while scan < self.heap.len() {
+            let ptr = scan + 1;
+            let header = self.get_obj_header(ptr);
+            let size = object_words(header) + 1;
+            if marked[ptr] {
+                let dest = forward[ptr];
+                self.heap.copy_within(scan..(scan + size), dest - 1);
+                if has_pointer_fields(header) {
+                    for field in &mut self.heap[dest..(dest + header.object_size as usize)] {
+                        if is_ptr(*field) {
+                            *field = ptr_value(forward[as_ptr(*field)]);
+                        }
+                    }
+                }
+            }
+            scan += size;
+        }
+
+        self.stats.collections += 1;
+        self.stats.freed_words += self.heap.len() - free;
+        self.stats.live_words = free;
+        self.heap.truncate(free);
+        self.next_collection = MIN_COLLECTION_THRESHOLD.max(free * 2);
+    }
+
+    // The data segment sits at the start of the program; every string in it
+    // is copied onto the heap, and every itable into `itables`, before the
+    // text segment starts running.
+    fn load_data(&mut self, code: &[Opcode]) {
+        while let Some(&Opcode::BeginItable {
+            type_index,
+            interface,
+        }) = code.get(self.pc)
+        {
+            self.pc += 1;
+            let mut methods = Vec::new();
+            while let Some(&Opcode::ItableEntry(address)) = code.get(self.pc) {
+                methods.push(address);
+                self.pc += 1;
+            }
+            self.itables.insert((type_index, interface), methods);
+        }
+
+        while let Some(&Opcode::BeginStringData(len)) = code.get(self.pc) {
+            self.heap.push(
+                ObjectHeader {
+                    type_index: STRING_TYPE_INDEX,
+                    object_size: len as u32,
+                }
+                .to_bits(),
+            );
+            self.strings.push(self.heap.len());
+            self.pc += 1;
+
+            for _ in 0..len.div_ceil(8) {
+                match code[self.pc] {
+                    Opcode::StringData(word) => self.heap.push(word),
+                    x => panic!("expected string data, got {:?}", x),
+                }
+                self.pc += 1;
+            }
         }
     }
 
@@ -112,47 +490,185 @@ where
         };
     }
 
-    fn run_op(&mut self, op: Opcode) {
+    fn run_op(&mut self, op: Opcode) -> Result<(), RuntimeError> {
         if DEBUG {
             println!("DEBUG: {:?}", op);
         }
 
         use Opcode::*;
         match op {
-            BeginStringData(_) | StringData(_) => {
-                panic!("StringDdata not supported in text section");
+            Trap(error) => return Err(RuntimeError::Trap(error)),
+            BeginStringData(_) | StringData(_) | BeginItable { .. } | ItableEntry(_) => {
+                panic!("data not supported in text section");
             }
-            MakeInt(int) => self.make_int(int),
-            MakeFloat(float) => self.make_float(float),
-            MakeBool(boolean) => {
-                self.heap.push(BOOL_HEADER.to_bits());
-                let ret_val = self.heap.len();
-                self.heap.push(boolean as u64);
-                self.stack.push(ret_val);
+            MakeInt(int) => self.push_int(Some(int))?,
+            MakeFloat(float) => self.push_float(float),
+            MakeStr(id) => {
+                self.stack.push(ptr_value(self.strings[id as usize]));
             }
+            MakeBool(boolean) => self.push_bool(boolean),
             SubFloat => {
-                let float2 = f64::from_bits(self.heap[self.stack.pop().unwrap()]);
-                let float1 = f64::from_bits(self.heap[self.stack.pop().unwrap()]);
-                self.make_float(float1 - float2);
+                let (float1, float2) = self.pop_floats()?;
+                self.push_float(float1 - float2);
             }
             SubInt => {
-                let int2 = self.heap[self.stack.pop().unwrap()] as i64;
-                let int1 = self.heap[self.stack.pop().unwrap()] as i64;
-                self.make_int(int1 - int2);
+                let (int1, int2) = self.pop_ints()?;
+                self.push_int(int1.checked_sub(int2))?;
             }
             AddFloat => {
-                let float2 = f64::from_bits(self.heap[self.stack.pop().unwrap()]);
-                let float1 = f64::from_bits(self.heap[self.stack.pop().unwrap()]);
-                self.make_float(float1 + float2);
+                let (float1, float2) = self.pop_floats()?;
+                self.push_float(float1 + float2);
             }
             AddInt => {
-                let int2 = self.heap[self.stack.pop().unwrap()] as i64;
-                let int1 = self.heap[self.stack.pop().unwrap()] as i64;
-                self.make_int(int1 + int2);
+                let (int1, int2) = self.pop_ints()?;
+                self.push_int(int1.checked_add(int2))?;
+            }
+            MulFloat => {
+                let (float1, float2) = self.pop_floats()?;
+                self.push_float(float1 * float2);
+            }
+            MulInt => {
+                let (int1, int2) = self.pop_ints()?;
+                self.push_int(int1.checked_mul(int2))?;
+            }
+            DivFloat => {
+                let (float1, float2) = self.pop_floats()?;
+                if float2 == 0.0 {
+                    return Err(self.fault("float division by zero"));
+                }
+                self.push_float(float1 / float2);
+            }
+            DivInt => {
+                let (int1, int2) = self.pop_ints()?;
+                if int2 == 0 {
+                    return Err(self.fault("division by zero"));
+                }
+                self.push_float(int1 as f64 / int2 as f64);
+            }
+            FloorDivFloat => {
+                let (float1, float2) = self.pop_floats()?;
+                if float2 == 0.0 {
+                    return Err(self.fault("float floor division by zero"));
+                }
+                self.push_float((float1 / float2).floor());
+            }
+            FloorDivInt => {
+                let (int1, int2) = self.pop_ints()?;
+                if int2 == 0 {
+                    return Err(self.fault("integer division by zero"));
+                }
+
+                // Python rounds towards negative infinity
+                let quotient = int1 / int2;
+                if int1 % int2 != 0 && (int1 < 0) != (int2 < 0) {
+                    self.push_int(Some(quotient - 1))?;
+                } else {
+                    self.push_int(Some(quotient))?;
+                }
+            }
+            ModFloat => {
+                let (float1, float2) = self.pop_floats()?;
+                if float2 == 0.0 {
+                    return Err(self.fault("float modulo by zero"));
+                }
+                self.push_float(float1 - float2 * (float1 / float2).floor());
+            }
+            ModInt => {
+                let (int1, int2) = self.pop_ints()?;
+                if int2 == 0 {
+                    return Err(self.fault("integer modulo by zero"));
+                }
+
+                // The result takes the sign of the divisor, like in Python
+                let remainder = int1 % int2;
+                if remainder != 0 && (remainder < 0) != (int2 < 0) {
+                    self.push_int(Some(remainder + int2))?;
+                } else {
+                    self.push_int(Some(remainder))?;
+                }
+            }
+            PowFloat => {
+                let (float1, float2) = self.pop_floats()?;
+                self.push_float(float1.powf(float2));
+            }
+            PowInt => {
+                let (int1, int2) = self.pop_ints()?;
+                if int2 < 0 {
+                    return Err(self.fault("negative exponent for integer power"));
+                }
+                if int2 > u32::MAX as i64 {
+                    return Err(self.fault("integer overflow"));
+                }
+                self.push_int(int1.checked_pow(int2 as u32))?;
+            }
+            NegFloat => {
+                let float = self.pop_float()?;
+                self.push_float(-float);
+            }
+            NegInt => {
+                let int = self.pop_int()?;
+                self.push_int(int.checked_neg())?;
+            }
+            Concat => {
+                let str2 = self.pop_str()?;
+                let str1 = self.pop_str()?;
+                let value = String::from(self.get_str(str1)) + self.get_str(str2);
+                self.make_str(&value);
+            }
+            Not => {
+                let value = self.stack.pop().unwrap();
+                let value = !self.eval_bool(value)?;
+                self.push_bool(value);
+            }
+            Equal => {
+                let result = self.pop_compare()? == Some(Ordering::Equal);
+                self.push_bool(result);
+            }
+            NotEqual => {
+                let result = self.pop_compare()? != Some(Ordering::Equal);
+                self.push_bool(result);
+            }
+            Less => {
+                let result = self.pop_compare()? == Some(Ordering::Less);
+                self.push_bool(result);
+            }
+            LessEqual => {
+                let result = matches!(
+                    self.pop_compare()?,
+                    Some(Ordering::Less) | Some(Ordering::Equal)
+                );
+                self.push_bool(result);
+            }
+            Greater => {
+                let result = self.pop_compare()? == Some(Ordering::Greater);
+                self.push_bool(result);
+            }
+            GreaterEqual => {
+                let result = matches!(
+                    self.pop_compare()?,
+                    Some(Ordering::Greater) | Some(Ordering::Equal)
+                );
+                self.push_bool(result);
             }
             Pop => {
                 self.stack.pop();
             }
+            Dup => {
+                self.stack.push(*self.stack.last().unwrap());
+            }
+            Swap => {
+                let len = self.stack.len();
+                self.stack.swap(len - 1, len - 2);
+            }
+            Rot => {
+                let top = self.stack.pop().unwrap();
+                let len = self.stack.len();
+                self.stack.insert(len - 2, top);
+            }
+            Pick(depth) => {
+                let len = self.stack.len();
+                self.stack.push(self.stack[len - 1 - depth as usize]);
+            }
             GetGlobal { stack_offset } => {
                 self.stack.push(self.stack[stack_offset as usize]);
             }
@@ -167,64 +683,98 @@ where
                 self.stack[self.fp.wrapping_add(stack_offset as usize)] = self.stack.pop().unwrap();
             }
             HeapRead { offset } => {
-                let ptr = self.stack.pop().unwrap();
-                let value = self.heap[ptr + offset as usize] as usize;
+                let ptr = self.pop_object()?;
+                let value = self.heap[ptr + offset as usize];
                 if DEBUG {
                     println!("       reading value: {}", value);
                 }
                 self.stack.push(value);
             }
             HeapWrite { offset } => {
-                let ptr = self.stack.pop().unwrap();
+                let ptr = self.pop_object()?;
                 let value = self.stack.pop().unwrap();
-                self.heap[ptr + offset as usize] = value as u64;
+                self.heap[ptr + offset as usize] = value;
             }
             HeapAlloc { header } => {
                 self.heap.push(header.to_bits());
                 let ret_val = self.heap.len();
                 for _ in 0..header.object_size {
-                    self.heap.push(NONE_VALUE as u64);
+                    self.heap.push(NONE_VALUE);
                 }
-                self.stack.push(ret_val);
+                self.stack.push(ptr_value(ret_val));
             }
             PushNone => {
                 self.stack.push(NONE_VALUE);
             }
             Jump(address) => {
                 self.pc = address as usize;
-                return;
+                return Ok(());
+            }
+            ForRange(address) => {
+                let len = self.stack.len();
+                if !self.stack[len - 3..].iter().all(|&value| is_int(value)) {
+                    return Err(self.fault("unsupported operand type(s): None"));
+                }
+                let counter = as_int(self.stack[len - 3]);
+                let stop = as_int(self.stack[len - 2]);
+                let step = as_int(self.stack[len - 1]);
+                if step == 0 {
+                    return Err(self.fault("range() arg 3 must not be zero"));
+                }
+
+                let more = if step > 0 {
+                    counter < stop
+                } else {
+                    counter > stop
+                };
+                if !more {
+                    self.pc = address as usize;
+                    return Ok(());
+                }
+
+                // A counter that would overflow is past the stop anyway
+                let next = counter.checked_add(step).and_then(int_value);
+                self.stack[len - 3] = next.unwrap_or(self.stack[len - 2]);
+                self.push_int(Some(counter))?;
             }
             JumpNotIf(address) => {
                 let arg = self.stack.pop().unwrap();
 
-                if !self.eval_bool(arg) {
+                if !self.eval_bool(arg)? {
                     self.pc = address as usize;
-                    return;
+                    return Ok(());
                 }
             }
             JumpIf(address) => {
                 let arg = self.stack.pop().unwrap();
 
-                if self.eval_bool(arg) {
+                if self.eval_bool(arg)? {
                     self.pc = address as usize;
-                    return;
+                    return Ok(());
                 }
             }
             CallDyn => {
                 let func = self.stack.pop().unwrap();
-                let func_header = self.get_obj_header(func);
-                if func_header != INT_HEADER {
-                    panic!("need to have int header for jump call");
+                if !is_int(func) {
+                    return Err(self.fault("called value isn't a function"));
                 }
 
-                let func = self.heap[func] as usize;
-
                 self.fp_ra_stack.push(self.pc + 1);
                 self.fp_ra_stack.push(self.fp);
 
-                self.pc = func;
+                self.pc = as_int(func) as usize;
                 self.fp = self.stack.len();
-                return;
+                return Ok(());
+            }
+            FunctionAddress(address) => self.push_int(Some(address as i64))?,
+            InterfaceMethod { interface, method } => {
+                let object = self.pop_object()?;
+                let type_index = self.get_obj_header(object).type_index;
+                let address = match self.itables.get(&(type_index, interface)) {
+                    Some(methods) => methods[method as usize],
+                    None => return Err(self.fault("object doesn't implement this interface")),
+                };
+                self.push_int(Some(address as i64))?;
             }
             Call(func) => {
                 self.fp_ra_stack.push(self.pc + 1);
@@ -232,7 +782,11 @@ where
 
                 self.pc = func as usize;
                 self.fp = self.stack.len();
-                return;
+                return Ok(());
+            }
+            Halt => {
+                self.pc = EXIT_ADDRESS;
+                return Ok(());
             }
             Return => {
                 while self.stack.len() > self.fp {
@@ -241,106 +795,384 @@ where
 
                 self.fp = self.fp_ra_stack.pop().unwrap();
                 self.pc = self.fp_ra_stack.pop().unwrap();
-                return;
+                return Ok(());
             }
-            ECall => match self.heap[self.stack.pop().unwrap()] {
+            ECall => match as_int(self.stack.pop().unwrap()) as u64 {
                 PRINT_PRIMITIVE => {
                     let arg = self.stack.pop().unwrap();
-                    let type_id = self.get_obj_header(arg);
-                    let arg_value = self.heap[arg];
-
-                    match type_id {
-                        INT_HEADER => {
-                            write!(self.stdout, "{}\n", arg_value as i64)
-                                .expect("should not have failed");
-                        }
-                        FLOAT_HEADER => {
-                            let float_value = f64::from_bits(arg_value);
-                            if float_value as i64 as f64 == float_value {
-                                write!(self.stdout, "{:.p$}\n", f64::from_bits(arg_value), p = 1)
-                                    .expect("should not have failed");
-                            } else {
-                                write!(self.stdout, "{}\n", f64::from_bits(arg_value))
-                                    .expect("should not have failed");
-                            }
-                        }
-                        BOOL_HEADER => {
-                            let value = if arg_value != 0 { "True" } else { "False" };
-                            write!(self.stdout, "{}\n", value).expect("should not have failed");
-                        }
-                        ObjectHeader {
-                            type_index: STRING_TYPE_INDEX,
-                            object_size,
-                        } => {
-                            let str_begin = (&self.heap[arg]) as *const u64 as *const u8;
-                            unsafe {
-                                let str_bytes =
-                                    slice::from_raw_parts(str_begin, object_size as usize);
-                                write!(
-                                    self.stdout,
-                                    "{}\n",
-                                    std::str::from_utf8_unchecked(str_bytes)
-                                )
-                                .expect("should not have failed");
-                            }
-                        }
-                        FUNCTION_HEADER => {
-                            write!(self.stdout, "function\n").expect("should not have failed")
-                        }
-                        x => panic!("got print_primitive ecall arg of invalid type {:?}", x),
-                    }
+                    let value = self.format_value(arg)?;
+                    writeln!(self.stdout, "{}", value).expect("should not have failed");
                     self.stack.push(NONE_VALUE);
                 }
                 FLOAT_CAST => {
                     let arg = self.stack.pop().unwrap();
-                    let type_id = self.get_obj_header(arg);
-                    let arg_value = self.heap[arg];
-
-                    match type_id {
-                        INT_HEADER => {
-                            self.heap.push(FLOAT_HEADER.to_bits());
-                            let ret_val = self.heap.len();
-                            self.heap.push((arg_value as i64 as f64).to_bits());
-                            self.stack.push(ret_val);
-                        }
-                        x => {
-                            panic!("[ECALL]: attempted to cast a non-int to float: {:?}", x);
-                        }
+                    let value = self.to_float(arg)?;
+                    self.push_float(value);
+                }
+                INT_CAST => {
+                    let arg = self.stack.pop().unwrap();
+                    let value = self.to_int(arg)?;
+                    self.push_int(Some(value))?;
+                }
+                BOOL_CAST => {
+                    let arg = self.stack.pop().unwrap();
+                    let value = self.eval_bool(arg)?;
+                    self.push_bool(value);
+                }
+                STR_CAST => {
+                    let arg = self.stack.pop().unwrap();
+                    if self.is_str(arg) {
+                        self.stack.push(arg);
+                    } else {
+                        let value = self.format_value(arg)?;
+                        self.make_str(&value);
                     }
                 }
-                _ => {
-                    println!("invalid ecall");
-                    panic!();
+                ecall if ecall >= HOST_ECALL_BEGIN => {
+                    let host_call = self.host_calls.get((ecall - HOST_ECALL_BEGIN) as usize);
+                    let (arity, return_type, call) = match host_call {
+                        Some((arity, return_type, call)) => (*arity, *return_type, call.clone()),
+                        None => return Err(self.fault("invalid ecall")),
+                    };
+
+                    let mut arguments = Vec::new();
+                    for _ in 0..arity {
+                        let arg = self.stack.pop().unwrap();
+                        arguments.push(self.host_value(arg));
+                    }
+                    match call(&arguments) {
+                        Ok(value) if value.is_a(return_type) => self.push_host_value(value)?,
+                        Ok(_) => return Err(self.fault("host function returned the wrong type")),
+                        Err(message) => return Err(self.fault(message)),
+                    }
                 }
+                _ => return Err(self.fault("invalid ecall")),
             },
         }
         self.pc += 1;
+        return Ok(());
     }
 
-    fn make_int(&mut self, value: i64) {
-        self.heap.push(INT_HEADER.to_bits());
-        let ret_val = self.heap.len();
-        self.heap.push(value as u64);
-        self.stack.push(ret_val);
+    fn get_str(&self, idx: usize) -> &str {
+        let len = self.get_obj_header(idx).object_size as usize;
+        let words = &self.heap[idx..(idx + len.div_ceil(8))];
+        unsafe {
+            let bytes = slice::from_raw_parts(words.as_ptr() as *const u8, len);
+            return std::str::from_utf8_unchecked(bytes);
+        }
+    }
+
+    // What `print` and `str` show for a value
+    fn format_value(&self, value: u64) -> Result<String, RuntimeError> {
+        if is_int(value) {
+            return Ok(as_int(value).to_string());
+        } else if is_float(value) {
+            return Ok(format_float(as_float(value)));
+        } else if is_bool(value) {
+            let value = if as_bool(value) { "True" } else { "False" };
+            return Ok(String::from(value));
+        } else if value == NONE_VALUE {
+            return Ok(String::from("None"));
+        }
+
+        return match self.get_obj_header(as_ptr(value)) {
+            ObjectHeader {
+                type_index: STRING_TYPE_INDEX,
+                ..
+            } => Ok(String::from(self.get_str(as_ptr(value)))),
+            FUNCTION_HEADER => Ok(String::from("function")),
+            header if header.type_index >= FIRST_INSTANCE_TYPE_INDEX => {
+                Ok(String::from("<object>"))
+            }
+            _ => Err(self.fault("value can't be printed")),
+        };
+    }
+
+    fn to_float(&self, value: u64) -> Result<f64, RuntimeError> {
+        if is_int(value) {
+            return Ok(as_int(value) as f64);
+        } else if is_float(value) {
+            return Ok(as_float(value));
+        } else if is_bool(value) {
+            return Ok(if as_bool(value) { 1.0 } else { 0.0 });
+        } else if !self.is_str(value) {
+            return Err(self.fault("float() argument must be a number, bool or str"));
+        }
+
+        // Rust's float syntax is Python's, minus the underscores
+        let text = self.get_str(as_ptr(value)).trim();
+        return match text.parse::<f64>() {
+            Ok(value) => Ok(value),
+            Err(_) => Err(self.fault("invalid literal for float()")),
+        };
+    }
+
+    // Floats are truncated towards zero, and strings have to hold a base 10
+    // integer, optionally signed, with single underscores between digits
+    fn to_int(&self, value: u64) -> Result<i64, RuntimeError> {
+        if is_int(value) {
+            return Ok(as_int(value));
+        } else if is_float(value) {
+            let value = as_float(value);
+            if !value.is_finite() {
+                return Err(self.fault("cannot convert infinity or NaN to int"));
+            }
+            return Ok(value.trunc() as i64);
+        } else if is_bool(value) {
+            return Ok(as_bool(value) as i64);
+        } else if !self.is_str(value) {
+            return Err(self.fault("int() argument must be a number, bool or str"));
+        }
+
+        let text = self.get_str(as_ptr(value)).trim();
+        let (negative, digits) = match text.as_bytes().first() {
+            Some(b'-') => (true, &text[1..]),
+            Some(b'+') => (false, &text[1..]),
+            _ => (false, text),
+        };
+
+        let mut result: i64 = 0;
+        let mut prev = b'_';
+        for &byte in digits.as_bytes() {
+            if byte == b'_' && prev != b'_' {
+                prev = byte;
+                continue;
+            } else if !byte.is_ascii_digit() {
+                return Err(self.fault("invalid literal for int()"));
+            }
+
+            let digit = (byte - b'0') as i64;
+            let next = result.checked_mul(10).and_then(|r| r.checked_add(digit));
+            result = next.ok_or_else(|| self.fault("integer overflow"))?;
+            prev = byte;
+        }
+        if prev == b'_' {
+            return Err(self.fault("invalid literal for int()"));
+        }
+
+        return Ok(if negative { -result } else { result });
+    }
+
+    fn host_value(&self, value: u64) -> Value {
+        if is_int(value) {
+            return Value::Int(as_int(value));
+        } else if is_float(value) {
+            return Value::Float(as_float(value));
+        } else if is_bool(value) {
+            return Value::Bool(as_bool(value));
+        } else if value == NONE_VALUE {
+            return Value::None;
+        }
+        return Value::Str(String::from(self.get_str(as_ptr(value))));
     }
 
-    fn make_float(&mut self, value: f64) {
-        self.heap.push(FLOAT_HEADER.to_bits());
+    fn push_host_value(&mut self, value: Value) -> Result<(), RuntimeError> {
+        match value {
+            Value::None => self.stack.push(NONE_VALUE),
+            Value::Int(value) => self.push_int(Some(value))?,
+            Value::Float(value) => self.push_float(value),
+            Value::Bool(value) => self.push_bool(value),
+            Value::Str(value) => self.make_str(&value),
+        }
+        return Ok(());
+    }
+
+    fn push_int(&mut self, value: Option<i64>) -> Result<(), RuntimeError> {
+        let value = value.and_then(int_value);
+        self.stack
+            .push(value.ok_or_else(|| self.fault("integer overflow"))?);
+        return Ok(());
+    }
+
+    fn push_float(&mut self, value: f64) {
+        self.stack.push(float_value(value));
+    }
+
+    fn push_bool(&mut self, value: bool) {
+        self.stack.push(bool_value(value));
+    }
+
+    fn make_str(&mut self, value: &str) {
+        let bytes = value.as_bytes();
+        self.heap.push(
+            ObjectHeader {
+                type_index: STRING_TYPE_INDEX,
+                object_size: bytes.len() as u32,
+            }
+            .to_bits(),
+        );
         let ret_val = self.heap.len();
-        self.heap.push(value.to_bits());
-        self.stack.push(ret_val);
+        for chunk in bytes.chunks(8) {
+            let mut word = [0u8; 8];
+            word[..chunk.len()].copy_from_slice(chunk);
+            self.heap.push(u64::from_ne_bytes(word));
+        }
+        self.stack.push(ptr_value(ret_val));
     }
 
-    fn eval_bool(&self, value: usize) -> bool {
-        if value == NONE_VALUE {
-            return true;
+    fn pop_ints(&mut self) -> Result<(i64, i64), RuntimeError> {
+        let int2 = self.pop_int()?;
+        let int1 = self.pop_int()?;
+        return Ok((int1, int2));
+    }
+
+    fn pop_floats(&mut self) -> Result<(f64, f64), RuntimeError> {
+        let float2 = self.pop_float()?;
+        let float1 = self.pop_float()?;
+        return Ok((float1, float2));
+    }
+
+    // The type checker lets None in wherever a value is expected, so operands
+    // have to be checked before they're used
+    fn pop_int(&mut self) -> Result<i64, RuntimeError> {
+        let value = self.stack.pop().unwrap();
+        if !is_int(value) {
+            return Err(self.fault("unsupported operand type(s): None"));
         }
-        return match self.get_obj_header(value) {
-            INT_HEADER | BOOL_HEADER => self.heap[value] != 0,
-            FLOAT_HEADER => f64::from_bits(self.heap[value]) != 0.0,
-            x => {
-                panic!("attempting to use value as boolean with type: {:?}\n", x);
+        return Ok(as_int(value));
+    }
+
+    fn pop_float(&mut self) -> Result<f64, RuntimeError> {
+        let value = self.stack.pop().unwrap();
+        if !is_float(value) {
+            return Err(self.fault("unsupported operand type(s): None"));
+        }
+        return Ok(as_float(value));
+    }
+
+    fn pop_str(&mut self) -> Result<usize, RuntimeError> {
+        let value = self.stack.pop().unwrap();
+        if !self.is_str(value) {
+            return Err(self.fault("unsupported operand type(s): None"));
+        }
+        return Ok(as_ptr(value));
+    }
+
+    // Compares the top two values on the stack; None only compares equal to
+    // itself, and NaN doesn't compare at all.
+    fn pop_compare(&mut self) -> Result<Option<Ordering>, RuntimeError> {
+        let right = self.stack.pop().unwrap();
+        let left = self.stack.pop().unwrap();
+        if left == NONE_VALUE || right == NONE_VALUE {
+            if left == right {
+                return Ok(Some(Ordering::Equal));
+            }
+            return Ok(None);
+        }
+
+        if is_int(left) && is_int(right) {
+            return Ok(Some(as_int(left).cmp(&as_int(right))));
+        }
+        if is_float(left) && is_float(right) {
+            return Ok(as_float(left).partial_cmp(&as_float(right)));
+        }
+        if is_bool(left) && is_bool(right) {
+            return Ok(Some(as_bool(left).cmp(&as_bool(right))));
+        }
+        if self.is_str(left) && self.is_str(right) {
+            return Ok(Some(
+                self.get_str(as_ptr(left)).cmp(self.get_str(as_ptr(right))),
+            ));
+        }
+        // Objects are only equal to themselves
+        if self.is_object(left) && self.is_object(right) {
+            if left == right {
+                return Ok(Some(Ordering::Equal));
             }
+            return Ok(None);
+        }
+
+        return Err(self.fault("values of these types can't be compared"));
+    }
+
+    // Python's truthiness, for conditions, `not`, `and`, `or` and `bool()`.
+    // NaN counts as true, since it isn't zero.
+    fn eval_bool(&self, value: u64) -> Result<bool, RuntimeError> {
+        if value == NONE_VALUE {
+            return Ok(false);
+        }
+        if is_int(value) {
+            return Ok(as_int(value) != 0);
+        }
+        if is_float(value) {
+            return Ok(as_float(value) != 0.0);
+        }
+        if is_bool(value) {
+            return Ok(as_bool(value));
+        }
+        if self.is_str(value) {
+            return Ok(self.get_obj_header(as_ptr(value)).object_size != 0);
+        }
+        if self.is_object(value) {
+            return Ok(true);
+        }
+
+        return Err(self.fault("value can't be used as a condition"));
+    }
+
+    fn is_str(&self, value: u64) -> bool {
+        return is_ptr(value) && self.get_obj_header(as_ptr(value)).type_index == STRING_TYPE_INDEX;
+    }
+
+    // Whether `value` is an instance of a class
+    fn is_object(&self, value: u64) -> bool {
+        return is_ptr(value)
+            && self.get_obj_header(as_ptr(value)).type_index >= FIRST_INSTANCE_TYPE_INDEX;
+    }
+
+    // None can end up anywhere the type checker expects an object, same as
+    // with any other type
+    fn pop_object(&mut self) -> Result<usize, RuntimeError> {
+        let value = self.stack.pop().unwrap();
+        if !is_ptr(value) {
+            return Err(self.fault("None has no members"));
+        }
+        return Ok(as_ptr(value));
+    }
+
+    fn fault(&self, message: &'static str) -> RuntimeError {
+        return RuntimeError::Fault {
+            pc: self.pc,
+            message,
         };
     }
 }
+
+// Like Python's `repr()`: the shortest digits that read back as the same
+// float, in scientific notation if the exponent is below -4 or at least 16
+fn format_float(value: f64) -> String {
+    if value.is_nan() {
+        return String::from("nan");
+    }
+    if value.is_infinite() {
+        return String::from(if value > 0.0 { "inf" } else { "-inf" });
+    }
+
+    let scientific = format!("{:e}", value);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+    if value != 0.0 && !(-4..16).contains(&exponent) {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        return format!("{}e{}{:02}", mantissa, sign, exponent.abs());
+    }
+
+    let positional = value.to_string();
+    if positional.contains('.') {
+        return positional;
+    }
+    return positional + ".0";
+}
+
+// Every field of anything but a string is a value, so only the ones tagged as
+// pointers get traced.
+fn has_pointer_fields(header: ObjectHeader) -> bool {
+    return header.type_index != STRING_TYPE_INDEX;
+}
+
+// Strings store their length in bytes, everything else in words
+fn object_words(header: ObjectHeader) -> usize {
+    if header.type_index == STRING_TYPE_INDEX {
+        return (header.object_size as usize).div_ceil(8);
+    }
+    return header.object_size as usize;
+}
@@ -10,6 +10,10 @@ pub enum Expr<'a> {
         value: f64,
         view: CRange,
     },
+    StringLiteral {
+        value: &'a str,
+        view: CRange,
+    },
     None(CRange),
     True(CRange),
     False(CRange),
@@ -28,45 +32,102 @@ pub enum Expr<'a> {
         member_id: u32,
         member_view: CRange,
     },
+    MethodCall {
+        parent: &'a mut Expr<'a>,
+        method: u32,
+        method_view: CRange,
+        arguments: &'a mut [Expr<'a>],
+        arguments_view: CRange,
+    },
     Tup {
         values: &'a mut [Expr<'a>],
         view: CRange,
     },
-    Minus {
+    Binary {
+        op: BinOp,
         left: &'a mut Expr<'a>,
         right: &'a mut Expr<'a>,
         view: CRange,
     },
-    Add {
-        left: &'a mut Expr<'a>,
-        right: &'a mut Expr<'a>,
+    Unary {
+        op: UnaryOp,
+        operand: &'a mut Expr<'a>,
+        view: CRange,
+    },
+    Compare {
+        first: &'a mut Expr<'a>,
+        rest: &'a mut [Comparison<'a>],
         view: CRange,
     },
 }
 
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    FloorDiv,
+    Mod,
+    Pow,
+    And,
+    Or,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum UnaryOp {
+    Neg,
+    Pos,
+    Not,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+// One link of a comparison chain; `a < b <= c` is `a` followed by the
+// comparisons `< b` and `<= c`.
+#[derive(Debug)]
+pub struct Comparison<'a> {
+    pub op: CmpOp,
+    pub right: Expr<'a>,
+}
+
 impl<'a> Expr<'a> {
     pub fn view(&self) -> CRange {
         use Expr::*;
         return match self {
             Int { view, .. } => *view,
             Float { view, .. } => *view,
-            Ident { id, view } => *view,
+            StringLiteral { view, .. } => *view,
+            Ident { view, .. } => *view,
             None(view) => *view,
             True(view) => *view,
             False(view) => *view,
             Call {
-                callee,
                 callee_view,
-                arguments,
                 arguments_view,
+                ..
             } => joinr(*callee_view, *arguments_view),
             DotAccess {
                 parent,
                 member_view,
                 ..
             } => joinr(parent.view(), *member_view),
-            Add { view, .. } => *view,
-            Minus { view, .. } => *view,
+            MethodCall {
+                parent,
+                arguments_view,
+                ..
+            } => joinr(parent.view(), *arguments_view),
+            Binary { view, .. } => *view,
+            Unary { view, .. } => *view,
+            Compare { view, .. } => *view,
             Tup { view, .. } => *view,
         };
     }
@@ -75,10 +136,21 @@ impl<'a> Expr<'a> {
 #[derive(Debug)]
 pub struct FuncParam {
     pub name: u32,
-    pub type_name: u32,
+    pub type_name: Option<u32>,
     pub view: CRange,
 }
 
+// A field in a class body, i.e. `x: int = 0`; fields without a default start
+// out as None
+#[derive(Debug)]
+pub struct ClassField<'a> {
+    pub name: u32,
+    pub name_view: CRange,
+    pub type_name: u32,
+    pub type_view: CRange,
+    pub default: Option<&'a mut Expr<'a>>,
+}
+
 #[derive(Debug)]
 pub struct IfBranch<'a> {
     pub condition: Expr<'a>,
@@ -87,7 +159,7 @@ pub struct IfBranch<'a> {
 
 #[derive(Debug)]
 pub enum Stmt<'a> {
-    Pass,
+    Pass(CRange),
     Expr(&'a mut Expr<'a>),
     Declare {
         name: u32,
@@ -104,6 +176,13 @@ pub enum Stmt<'a> {
         return_type: Option<u32>,
         stmts: &'a mut [Stmt<'a>],
     },
+    // Every method is a `Stmt::Function`
+    Class {
+        name: u32,
+        name_view: CRange,
+        fields: &'a mut [ClassField<'a>],
+        methods: &'a mut [Stmt<'a>],
+    },
     Assign {
         to: u32,
         to_view: CRange,
@@ -112,6 +191,7 @@ pub enum Stmt<'a> {
     AssignMember {
         to: &'a mut Expr<'a>,
         to_member: u32,
+        to_member_view: CRange,
         value: &'a mut Expr<'a>,
     },
     If {
@@ -123,12 +203,47 @@ pub enum Stmt<'a> {
         block: &'a mut [Stmt<'a>],
         else_branch: &'a mut [Stmt<'a>],
     },
-    Break,
+    For {
+        target: u32,
+        target_view: CRange,
+        iterable: &'a mut Expr<'a>,
+        block: &'a mut [Stmt<'a>],
+        else_branch: &'a mut [Stmt<'a>],
+    },
+    Break(CRange),
+    Continue(CRange),
     Return {
         ret_val: &'a mut Expr<'a>,
     },
 }
 
+impl<'a> Stmt<'a> {
+    // Compound statements are located by their header
+    pub fn view(&self) -> CRange {
+        use Stmt::*;
+        return match self {
+            Pass(view) | Break(view) | Continue(view) => *view,
+            Expr(expr) => expr.view(),
+            Declare {
+                name_view, value, ..
+            } => joinr(*name_view, value.view()),
+            Function { name_view, .. } | Class { name_view, .. } => *name_view,
+            Assign { to_view, value, .. } => joinr(*to_view, value.view()),
+            AssignMember { to, value, .. } => joinr(to.view(), value.view()),
+            If {
+                conditioned_blocks, ..
+            } => conditioned_blocks[0].condition.view(),
+            While { condition, .. } => condition.view(),
+            For {
+                target_view,
+                iterable,
+                ..
+            } => joinr(*target_view, iterable.view()),
+            Return { ret_val } => ret_val.view(),
+        };
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum Type<'a> {
     None,
@@ -136,17 +251,94 @@ pub enum Type<'a> {
     Int,
     Float,
     Bool,
+    Str,
     Function {
         return_type: &'a Type<'a>,
         arguments: &'a [Type<'a>],
     },
+    // Instances of the class with this uid
+    Class(u32),
+    // Any object with these methods, sorted by name. Interfaces are
+    // inferred from the methods that get called on parameters.
+    Interface(&'a [InterfaceMethod<'a>]),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct InterfaceMethod<'a> {
+    pub name: u32,
+    pub arguments: &'a [Type<'a>],
+    pub return_type: &'a Type<'a>,
 }
 
 impl<'a> Type<'a> {
     pub fn is_primitive(&self) -> bool {
+        return matches!(self, Type::Int | Type::Float);
+    }
+}
+
+impl<'a> std::fmt::Display for Type<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            Type::None => write!(f, "None"),
+            Type::Any => write!(f, "any"),
+            Type::Int => write!(f, "int"),
+            Type::Float => write!(f, "float"),
+            Type::Bool => write!(f, "bool"),
+            Type::Str => write!(f, "str"),
+            Type::Function {
+                return_type,
+                arguments,
+            } => {
+                write!(f, "(")?;
+                for (idx, arg) in arguments.iter().enumerate() {
+                    if idx != 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+                write!(f, ") -> {}", return_type)
+            }
+            Type::Class(_) => write!(f, "object"),
+            Type::Interface(_) => write!(f, "interface"),
+        };
+    }
+}
+
+impl BinOp {
+    pub fn symbol(self) -> &'static str {
+        return match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::FloorDiv => "//",
+            BinOp::Mod => "%",
+            BinOp::Pow => "**",
+            BinOp::And => "and",
+            BinOp::Or => "or",
+        };
+    }
+}
+
+impl UnaryOp {
+    pub fn symbol(self) -> &'static str {
+        return match self {
+            UnaryOp::Neg => "-",
+            UnaryOp::Pos => "+",
+            UnaryOp::Not => "not ",
+        };
+    }
+}
+
+impl CmpOp {
+    pub fn symbol(self) -> &'static str {
         return match self {
-            Type::Int | Type::Float => true,
-            _ => false,
+            CmpOp::Eq => "==",
+            CmpOp::Ne => "!=",
+            CmpOp::Lt => "<",
+            CmpOp::Le => "<=",
+            CmpOp::Gt => ">",
+            CmpOp::Ge => ">=",
         };
     }
 }
@@ -161,23 +353,75 @@ pub enum TExpr<'a> {
     Int(i64),
     Float(f64),
     Bool(bool),
-    Minus {
+    Str(&'a str),
+    Binary {
+        op: BinOp,
         left: &'a TExpr<'a>,
         right: &'a TExpr<'a>,
         type_: Type<'a>,
+        view: CRange,
     },
-    Add {
-        left: &'a TExpr<'a>,
-        right: &'a TExpr<'a>,
+    Unary {
+        op: UnaryOp,
+        operand: &'a TExpr<'a>,
         type_: Type<'a>,
+        view: CRange,
+    },
+    Compare {
+        first: &'a TExpr<'a>,
+        rest: &'a [TComparison<'a>],
+        view: CRange,
     },
     Call {
         callee_uid: u32,
         arguments: &'a [TExpr<'a>],
         type_: Type<'a>,
+        view: CRange,
+    },
+    // Allocates an instance, fills in its fields and calls `init` on it if
+    // the class has an `__init__`; `name` is the class's name
+    New {
+        class: u32,
+        name: u32,
+        fields: &'a [TExpr<'a>],
+        init: Option<u32>,
+        arguments: &'a [TExpr<'a>],
+        view: CRange,
+    },
+    // `field` is the index of the field in its class, and `name` is its name
+    Member {
+        object: &'a TExpr<'a>,
+        field: u32,
+        name: u32,
+        type_: Type<'a>,
+        view: CRange,
+    },
+    // `method` is the index of the method in its class
+    MethodCall {
+        object: &'a TExpr<'a>,
+        method: u32,
+        name: u32,
+        arguments: &'a [TExpr<'a>],
+        type_: Type<'a>,
+        view: CRange,
+    },
+    // Lets an object be used as an interface it implements
+    Upcast {
+        value: &'a TExpr<'a>,
+        interface: &'a [InterfaceMethod<'a>],
+    },
+    // `method` is the index of the method in the interface
+    InterfaceCall {
+        object: &'a TExpr<'a>,
+        interface: &'a [InterfaceMethod<'a>],
+        method: u32,
+        arguments: &'a [TExpr<'a>],
+        view: CRange,
     },
+    // The first argument says which ecall it is
     ECall {
         arguments: &'a [TExpr<'a>],
+        type_: Type<'a>,
     },
 }
 
@@ -189,15 +433,30 @@ impl<'a> TExpr<'a> {
             Int(_) => Type::Int,
             Float(_) => Type::Float,
             Bool(_) => Type::Bool,
+            Str(_) => Type::Str,
             None => Type::None,
-            Minus { type_, .. } => *type_,
-            Add { type_, .. } => *type_,
+            Binary { type_, .. } => *type_,
+            Unary { type_, .. } => *type_,
+            Compare { .. } => Type::Bool,
             Call { type_, .. } => *type_,
-            ECall { .. } => Type::None,
+            New { class, .. } => Type::Class(*class),
+            Member { type_, .. } => *type_,
+            MethodCall { type_, .. } => *type_,
+            Upcast { interface, .. } => Type::Interface(interface),
+            InterfaceCall {
+                interface, method, ..
+            } => *interface[*method as usize].return_type,
+            ECall { type_, .. } => *type_,
         };
     }
 }
 
+#[derive(Debug, Clone, Copy)]
+pub struct TComparison<'a> {
+    pub op: CmpOp,
+    pub right: TExpr<'a>,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Declaration {
     pub name: u32,
@@ -210,6 +469,13 @@ pub enum TStmt<'a> {
         to: u32,
         value: &'a TExpr<'a>,
     },
+    AssignMember {
+        object: &'a TExpr<'a>,
+        field: u32,
+        name: u32,
+        value: &'a TExpr<'a>,
+        view: CRange,
+    },
     Function {
         uid: u32,
         name: u32,
@@ -217,6 +483,13 @@ pub enum TStmt<'a> {
         declarations: &'a [Declaration],
         stmts: &'a [TStmt<'a>],
     },
+    // The methods are `TStmt::Function`s, in the order instances look them
+    // up in
+    Class {
+        uid: u32,
+        name: u32,
+        methods: &'a [TStmt<'a>],
+    },
     If {
         condition: &'a TExpr<'a>,
         if_true: &'a [TStmt<'a>],
@@ -227,10 +500,27 @@ pub enum TStmt<'a> {
         block: &'a [TStmt<'a>],
         else_block: &'a [TStmt<'a>],
     },
+    // Counts `var` from `start` towards `stop` in steps of `step`, like
+    // Python's `range`; `view` is the call to `range`
+    ForRange {
+        var: u32,
+        start: &'a TExpr<'a>,
+        stop: &'a TExpr<'a>,
+        step: &'a TExpr<'a>,
+        block: &'a [TStmt<'a>],
+        else_block: &'a [TStmt<'a>],
+        view: CRange,
+    },
     Break,
+    Continue,
     Return {
         ret_val: &'a TExpr<'a>,
     },
+    // A statement that failed to type check; `error` is the index of its
+    // error in the list returned by the type checker.
+    Trap {
+        error: u32,
+    },
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -1,11 +1,15 @@
 use crate::builtins::*;
+use crate::flow;
+use crate::inference::*;
+use crate::runtime::{FLOAT_CAST, INT_MAX, INT_MIN};
 use crate::syntax_tree::Type;
 use crate::syntax_tree::*;
 use crate::util::*;
 use std::collections::HashMap;
+use std::mem;
 use std::ptr::NonNull;
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, Clone, Copy)]
 pub enum SymbolInfo<'a> {
     Function {
         uid: u32,
@@ -17,6 +21,37 @@ pub enum SymbolInfo<'a> {
         type_: &'a Type<'a>,
         view: CRange,
     },
+    Class {
+        info: &'a ClassInfo<'a>,
+        view: CRange,
+    },
+}
+
+// Instances hold their class's fields in order, and find their methods by
+// index
+#[derive(Debug)]
+pub struct ClassInfo<'a> {
+    pub uid: u32,
+    pub name: u32,
+    pub fields: &'a [FieldInfo<'a>],
+    pub methods: &'a [MethodInfo<'a>],
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FieldInfo<'a> {
+    pub name: u32,
+    pub type_: Type<'a>,
+    // Always a literal
+    pub default: TExpr<'a>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MethodInfo<'a> {
+    pub name: u32,
+    pub uid: u32,
+    // Not including the object the method is called on
+    pub arguments: &'a [Type<'a>],
+    pub return_type: &'a Type<'a>,
 }
 
 impl<'a> SymbolInfo<'a> {
@@ -31,6 +66,7 @@ impl<'a> SymbolInfo<'a> {
                 arguments,
             },
             &SymbolInfo::Variable { type_, .. } => *type_,
+            SymbolInfo::Class { info, .. } => Type::Class(info.uid),
         };
     }
 
@@ -39,6 +75,7 @@ impl<'a> SymbolInfo<'a> {
         return match self {
             Function { view, .. } => *view,
             Variable { view, .. } => *view,
+            Class { view, .. } => *view,
         };
     }
 }
@@ -63,34 +100,49 @@ impl<'a> SymbolTable<'a> {
         };
     }
 
-    pub fn fold_into_parent(mut self) -> Result<(), Error<'static>> {
+    // Moves every symbol into the parent scope, even if some of them conflict
+    // with names that are already there.
+    pub fn fold_into_parent(mut self) -> Vec<Error<'static>> {
+        let mut errors = Vec::new();
         for (symbol, info) in self.symbols.drain() {
-            unsafe { self.parent.unwrap().as_mut() }.declare(symbol, info)?;
+            if let Err(e) = unsafe { self.parent.unwrap().as_mut() }.declare(symbol, info) {
+                errors.push(e);
+            }
         }
-        return Ok(());
+        return errors;
     }
 
+    // When the two tables disagree on a variable's type, the left one wins and
+    // an error is reported.
     pub fn merge_parallel_tables<'b>(
         mut left: SymbolTable<'b>,
         mut right: SymbolTable<'b>,
-    ) -> Result<SymbolTable<'b>, Error<'static>> {
-        assert!(left.parent == right.parent && left.parent != None);
+    ) -> (SymbolTable<'b>, Vec<Error<'static>>) {
+        assert!(left.parent == right.parent && left.parent.is_some());
         let mut result = symbols_(unsafe { left.parent.unwrap().as_mut() });
+        let mut errors = Vec::new();
         for (id, info) in left.symbols.drain() {
             if let Some(rinfo) = right.symbols.remove(&id) {
                 if info.get_type() != rinfo.get_type() {
-                    return err(info.view(),
-                        "variable type differs from other variable type in parallel scope with same name");
+                    errors.push(Error {
+                        location: info.view(),
+                        message: "variable type differs from other variable type in parallel scope with same name",
+                        code: Code::Type,
+                    });
                 }
             }
-            result.declare(id, info)?;
+            result.symbols.insert(id, info);
         }
 
         for (id, info) in right.symbols.drain() {
-            result.declare(id, info)?;
+            result.symbols.insert(id, info);
         }
 
-        return Ok(result);
+        return (result, errors);
+    }
+
+    pub fn is_global(&self) -> bool {
+        return self.parent.is_none();
     }
 
     pub fn declare(&mut self, symbol: u32, info: SymbolInfo<'a>) -> Result<(), Error<'static>> {
@@ -129,7 +181,16 @@ where
     next_uid_: u32,
     buckets: &'a mut Buckets<'b>,
     types: HashMap<u32, &'b Type<'b>>,
+    errors: Vec<Error<'b>>,
+    // What the first error gets numbered as in the traps
+    first_error: u32,
     warnings: Vec<Error<'b>>,
+    silenced: Silenced,
+    signatures: HashMap<u32, Signature<'b>>,
+    classes: HashMap<u32, &'b ClassInfo<'b>>,
+    // Uids of the classes in the code being checked, by the start of their
+    // names
+    class_uids: HashMap<u32, u32>,
 }
 
 impl<'a, 'b> TypeChecker<'a, 'b>
@@ -141,7 +202,13 @@ where
             next_uid_: FUNC_UID_BEGIN,
             buckets,
             types: HashMap::new(),
+            errors: Vec::new(),
+            first_error: 0,
             warnings: Vec::new(),
+            silenced: Silenced::default(),
+            signatures: HashMap::new(),
+            classes: HashMap::new(),
+            class_uids: HashMap::new(),
         };
     }
 
@@ -151,84 +218,388 @@ where
         return ret_val;
     }
 
-    pub fn check_program(&mut self, program: &[Stmt]) -> Result<TProgram<'b>, Error<'b>> {
-        let type_table = builtin_types(self.buckets);
-        let symbol_table = builtin_symbols(self.buckets);
-        self.types = type_table;
+    // Statements that fail to check are replaced with traps, so the program
+    // can still run as long as it doesn't reach them. Whether it should run at
+    // all is up to the caller.
+    pub fn check_program(
+        &mut self,
+        host: &Host,
+        program: &[Stmt],
+    ) -> (TProgram<'b>, Vec<Error<'b>>) {
+        let symbol_table = builtin_symbols(self.buckets, host);
+        let mut sym = SymbolTable::new_global(symbol_table);
+        self.set_next_uid(host.first_free_uid());
 
-        let sym = SymbolTable::new_global(symbol_table);
+        let (program, errors) = self.check_entry(&mut sym, program);
+        let mut tstmts = program.stmts.to_vec();
+        tstmts.append(&mut builtin_definitions(self.buckets, host));
+        let program = TProgram {
+            declarations: program.declarations,
+            stmts: self.buckets.add_array(tstmts),
+        };
+        return (program, errors);
+    }
 
-        let (sym, mut tstmts) = self.check_stmts(false, program, sym, None)?;
+    // Checks top-level code against an existing global scope, which is left
+    // holding every global declared so far. `declarations` lists all of them,
+    // not just the new ones.
+    pub fn check_entry(
+        &mut self,
+        sym: &mut SymbolTable<'b>,
+        program: &[Stmt],
+    ) -> (TProgram<'b>, Vec<Error<'b>>) {
+        self.types = builtin_types(self.buckets);
+        for info in sym.symbols.values() {
+            if let SymbolInfo::Class { info, .. } = info {
+                self.types
+                    .insert(info.name, self.buckets.add(Type::Class(info.uid)));
+                self.classes.insert(info.uid, info);
+            }
+        }
+        self.add_class_types(program);
 
-        let declarations = sym
-            .symbols
-            .keys()
-            .map(|&name| Declaration { name })
-            .collect();
-        let declarations = self.buckets.add_array(declarations);
+        // Unannotated parameter and return types are filled in from the
+        // inferred signatures when the functions get declared
+        let inferrer = Inferrer::new(self.buckets, &self.types, &sym.symbols);
+        let (signatures, errors) = inferrer.infer_program(program);
+        self.signatures = signatures;
+        self.errors.extend(errors);
 
-        tstmts.append(&mut builtin_definitions(self.buckets));
-        let tstmts = self.buckets.add_array(tstmts);
-        return Ok(TProgram {
-            declarations,
-            stmts: tstmts,
-        });
+        let mut global = mem::replace(sym, SymbolTable::new_global(HashMap::new()));
+        for stmt in program {
+            if let Err(e) = self.add_class_symbol(&mut global, stmt) {
+                self.errors.push(e);
+            }
+        }
+        let (global, tstmts) = self.check_stmts(false, program, global, None);
+        *sym = global;
+
+        let declarations = declarations(&sym.symbols);
+        let program = TProgram {
+            declarations: self.buckets.add_array(declarations),
+            stmts: self.buckets.add_array(tstmts),
+        };
+        return (program, mem::take(&mut self.errors));
+    }
+
+    // Function uids have to stay unique across everything that gets
+    // assembled together, so checkers that share an assembler continue
+    // numbering where the last one stopped.
+    pub fn set_next_uid(&mut self, uid: u32) {
+        self.next_uid_ = uid;
+    }
+
+    pub fn next_free_uid(&self) -> u32 {
+        return self.next_uid_;
+    }
+
+    // Traps refer to errors by their index, so checkers whose code runs
+    // together number their errors after the ones before them, same as uids
+    pub fn set_first_error(&mut self, index: u32) {
+        self.first_error = index;
+    }
+
+    // Warnings don't stop anything from running, so they're kept separate
+    // from the errors
+    pub fn take_warnings(&mut self) -> Vec<Error<'b>> {
+        return mem::take(&mut self.warnings);
     }
 
-    fn add_function_symbols(
+    // Warnings in regions silenced by pragmas are dropped
+    pub fn set_silenced(&mut self, silenced: Silenced) {
+        self.silenced = silenced;
+    }
+
+    // The regions silenced by pragmas, and the errors that only follow from
+    // ones that were already reported, which trap without being reported
+    pub fn take_silenced(&mut self) -> Silenced {
+        return mem::take(&mut self.silenced);
+    }
+
+    fn warn(&mut self, location: CRange, code: Code, message: &'b str) {
+        if !self.silenced.contains(code, location) {
+            self.warnings.push(Error {
+                location,
+                message,
+                code,
+            });
+        }
+    }
+
+    fn add_function_symbols(&mut self, sym: &mut SymbolTable<'b>, stmts: &[Stmt]) {
+        for stmt in stmts {
+            if let Err(e) = self.add_function_symbol(sym, stmt) {
+                self.errors.push(e);
+            }
+        }
+    }
+
+    fn add_function_symbol(
         &mut self,
         sym: &mut SymbolTable<'b>,
-        stmts: &[Stmt],
+        stmt: &Stmt,
     ) -> Result<(), Error<'b>> {
+        if let Stmt::Function {
+            name,
+            name_view,
+            arguments,
+            return_type_view,
+            return_type,
+            ..
+        } = stmt
+        {
+            let (return_type, arguments) =
+                self.signature(name_view, arguments, *return_type, return_type_view)?;
+            sym.declare(
+                *name,
+                SymbolInfo::Function {
+                    uid: self.next_uid(),
+                    return_type,
+                    arguments,
+                    view: *name_view,
+                },
+            )?;
+        }
+        return Ok(());
+    }
+
+    // The declared types of a function, with the inferred ones filling in for
+    // missing annotations
+    fn signature(
+        &mut self,
+        name_view: &CRange,
+        arguments: &[FuncParam],
+        return_type: Option<u32>,
+        return_type_view: &CRange,
+    ) -> Result<(&'b Type<'b>, &'b [Type<'b>]), Error<'b>> {
+        let decl_return_type;
+        if let Some(return_type) = return_type {
+            decl_return_type = **unwrap_err(
+                self.types.get(&return_type),
+                *return_type_view,
+                "type doesn't exist",
+            )?;
+        } else {
+            decl_return_type = self.signature_return_type(name_view);
+        }
+
+        let decl_return_type = self.buckets.add(decl_return_type);
+
+        let mut arg_types = Vec::new();
+        for (idx, arg) in arguments.iter().enumerate() {
+            let arg_type = match &arg.type_name {
+                Some(type_name) => {
+                    **unwrap_err(self.types.get(type_name), arg.view, "type doesn't exist")?
+                }
+                None => self.signature_argument(name_view, idx),
+            };
+
+            arg_types.push(arg_type);
+        }
+
+        return Ok((decl_return_type, self.buckets.add_array(arg_types)));
+    }
+
+    // Class names are types everywhere in the module, so they're declared
+    // before anything else, including inference
+    fn add_class_types(&mut self, stmts: &[Stmt]) {
         for stmt in stmts {
-            match stmt {
+            if let Stmt::Class {
+                name, name_view, ..
+            } = stmt
+            {
+                if self.types.contains_key(name) {
+                    self.errors.push(Error {
+                        location: *name_view,
+                        message: "type with this name already exists",
+                        code: Code::Type,
+                    });
+                    continue;
+                }
+
+                let uid = self.next_uid();
+                self.types.insert(*name, self.buckets.add(Type::Class(uid)));
+                self.class_uids.insert(name_view.start, uid);
+            }
+        }
+    }
+
+    fn add_class_symbol(
+        &mut self,
+        sym: &mut SymbolTable<'b>,
+        stmt: &Stmt,
+    ) -> Result<(), Error<'b>> {
+        let (name, name_view, fields, methods) = match stmt {
+            Stmt::Class {
+                name,
+                name_view,
+                fields,
+                methods,
+            } => (name, name_view, fields, methods),
+            _ => return Ok(()),
+        };
+        let uid = match self.class_uids.get(&name_view.start) {
+            Some(&uid) => uid,
+            None => return Ok(()),
+        };
+
+        let mut member_names = Vec::new();
+        let mut field_infos = Vec::new();
+        for field in fields.iter() {
+            if member_names.contains(&field.name) {
+                return err(field.name_view, "class already has a member with this name");
+            }
+            member_names.push(field.name);
+
+            let type_ = **unwrap_err(
+                self.types.get(&field.type_name),
+                field.type_view,
+                "type doesn't exist",
+            )?;
+
+            // Defaults get copied into every new instance, wherever it's
+            // created, so they can't refer to anything
+            let default = match &field.default {
+                Some(value) => {
+                    let default = self.check_expr(sym, value)?;
+                    match default {
+                        TExpr::None
+                        | TExpr::Int(_)
+                        | TExpr::Float(_)
+                        | TExpr::Bool(_)
+                        | TExpr::Str(_) => {}
+                        _ => return err(value.view(), "field defaults need to be literals"),
+                    }
+                    self.cast_err(type_, default, value.view(), "default value is wrong type")?
+                }
+                None => TExpr::None,
+            };
+
+            field_infos.push(FieldInfo {
+                name: field.name,
+                type_,
+                default,
+            });
+        }
+
+        let mut method_infos = Vec::new();
+        for method in methods.iter() {
+            let (method_name, method_view, arguments, return_type, return_type_view) = match method
+            {
                 Stmt::Function {
                     name,
                     name_view,
                     arguments,
-                    return_type_view,
                     return_type,
-                    stmts,
-                } => {
-                    let decl_return_type;
-                    if let Some(return_type) = return_type {
-                        decl_return_type = **unwrap_err(
-                            self.types.get(return_type),
-                            *return_type_view,
-                            "type doesn't exist",
-                        )?;
-                    } else {
-                        decl_return_type = Type::None;
-                    }
-
-                    let decl_return_type = self.buckets.add(decl_return_type);
-
-                    let mut arg_types = Vec::new();
-                    for arg in arguments.iter() {
-                        let arg_type = **unwrap_err(
-                            self.types.get(&arg.type_name),
-                            arg.view,
-                            "type doesn't exist",
-                        )?;
+                    return_type_view,
+                    ..
+                } => (name, name_view, arguments, return_type, return_type_view),
+                _ => continue,
+            };
 
-                        arg_types.push(arg_type);
-                    }
+            if member_names.contains(method_name) {
+                return err(*method_view, "class already has a member with this name");
+            }
+            member_names.push(*method_name);
 
-                    let arg_types = self.buckets.add_array(arg_types);
-                    sym.declare(
-                        *name,
-                        SymbolInfo::Function {
-                            uid: self.next_uid(),
-                            return_type: decl_return_type,
-                            arguments: arg_types,
-                            view: *name_view,
-                        },
-                    )?;
+            let receiver = unwrap_err(
+                arguments.first(),
+                *method_view,
+                "methods need a first parameter for the object they're called on",
+            )?;
+            if let Some(type_name) = receiver.type_name {
+                if self.types.get(&type_name).copied() != Some(&Type::Class(uid)) {
+                    return err(
+                        receiver.view,
+                        "the first parameter of a method is its object",
+                    );
                 }
-                _ => {}
             }
+
+            let (return_type, arg_types) =
+                self.signature(method_view, arguments, *return_type, return_type_view)?;
+            if *method_name == INIT_IDX && *return_type != Type::None {
+                return err(*method_view, "__init__() should return None");
+            }
+
+            method_infos.push(MethodInfo {
+                name: *method_name,
+                uid: self.next_uid(),
+                arguments: &arg_types[1..],
+                return_type,
+            });
         }
-        return Ok(());
+
+        let fields = self.buckets.add_array(field_infos);
+        let methods = self.buckets.add_array(method_infos);
+        let info = self.buckets.add(ClassInfo {
+            uid,
+            name: *name,
+            fields,
+            methods,
+        });
+        self.classes.insert(uid, info);
+        return sym.declare(
+            *name,
+            SymbolInfo::Class {
+                info,
+                view: *name_view,
+            },
+        );
+    }
+
+    fn class_of(&mut self, type_: Type<'b>, view: CRange) -> Result<&'b ClassInfo<'b>, Error<'b>> {
+        return match type_ {
+            Type::Class(uid) => Ok(self.classes[&uid]),
+            Type::Interface(_) => err(view, "only methods can be used on this object"),
+            // Values are only of any type when their type couldn't be
+            // inferred, which has been reported already
+            Type::Any => {
+                self.silenced.add(Code::Type, view);
+                err(view, "value of this type has no members")
+            }
+            _ => err(view, "value of this type has no members"),
+        };
+    }
+
+    // Classes implement every interface whose methods they have, with the
+    // same types. Methods that can return anything can be implemented by ones
+    // that return something in particular.
+    fn implements(&self, uid: u32, interface: &[InterfaceMethod<'b>]) -> bool {
+        let info = self.classes[&uid];
+        return interface.iter().all(|method| {
+            info.methods.iter().any(|m| {
+                m.name == method.name
+                    && m.arguments == method.arguments
+                    && (m.return_type == method.return_type || *method.return_type == Type::Any)
+            })
+        });
+    }
+
+    // Same as for classes; an interface can be used as any interface whose
+    // methods it has
+    fn extends(&self, from: &[InterfaceMethod<'b>], to: &[InterfaceMethod<'b>]) -> bool {
+        return to.iter().all(|method| {
+            from.iter().any(|m| {
+                m.name == method.name
+                    && m.arguments == method.arguments
+                    && (m.return_type == method.return_type || *method.return_type == Type::Any)
+            })
+        });
+    }
+
+    fn signature_return_type(&self, name_view: &CRange) -> Type<'b> {
+        return match self.signatures.get(&name_view.start) {
+            Some(signature) => signature.return_type,
+            None => Type::None,
+        };
+    }
+
+    fn signature_argument(&self, name_view: &CRange, idx: usize) -> Type<'b> {
+        return match self.signatures.get(&name_view.start) {
+            Some(signature) => signature.arguments[idx],
+            None => Type::Any,
+        };
     }
 
     fn check_stmts(
@@ -237,206 +608,482 @@ where
         stmts: &[Stmt],
         mut sym: SymbolTable<'b>,
         return_type: Option<Type<'b>>,
-    ) -> Result<(SymbolTable<'b>, Vec<TStmt<'b>>), Error<'b>> {
-        self.add_function_symbols(&mut sym, stmts)?;
+    ) -> (SymbolTable<'b>, Vec<TStmt<'b>>) {
+        self.add_function_symbols(&mut sym, stmts);
         let mut tstmts = Vec::new();
+        let mut exited = false;
         for stmt in stmts {
-            match stmt {
-                Stmt::Pass => {}
-                Stmt::Expr(expr) => {
-                    let expr = self.check_expr(&mut sym, expr)?;
-                    let expr = self.buckets.add(expr);
-                    tstmts.push(TStmt::Expr(expr));
-                }
-                Stmt::Return { ret_val } => {
-                    let return_type = unwrap_err(
-                        return_type,
-                        ret_val.view(),
-                        "can't return a value from this context",
-                    )?;
+            // Only the first unreachable statement is worth pointing out
+            if exited {
+                self.warn(stmt.view(), Code::UnreachableCode, "unreachable code");
+                exited = false;
+            }
 
-                    let ret_val_view = ret_val.view();
-                    let ret_val = self.check_expr(&mut sym, ret_val)?;
-                    let ret_val = self.cast_err(
-                        return_type,
-                        ret_val,
-                        ret_val_view,
-                        "return value is wrong type",
-                    )?;
-                    let ret_val = self.buckets.add(ret_val);
+            let checked = tstmts.len();
+            if let Err(e) = self.check_stmt(in_loop, stmt, &mut sym, return_type, &mut tstmts) {
+                tstmts.push(TStmt::Trap {
+                    error: self.first_error + self.errors.len() as u32,
+                });
+                self.errors.push(e);
+            } else if tstmts[checked..]
+                .iter()
+                .any(|tstmt| flow::exits(tstmt, false))
+            {
+                exited = true;
+            }
+        }
 
-                    tstmts.push(TStmt::Return { ret_val });
-                }
-                Stmt::Declare {
-                    name,
-                    name_view,
-                    type_name,
-                    type_view,
-                    value,
-                } => {
-                    let decl_type =
-                        *unwrap_err(self.types.get(type_name), *type_view, "type doesn't exist")?;
-
-                    sym.declare(
-                        *name,
-                        SymbolInfo::Variable {
-                            type_: decl_type,
-                            view: *name_view,
-                        },
-                    )?;
+        return (sym, tstmts);
+    }
 
-                    let expr = self.check_expr(&mut sym, value)?;
-                    let expr =
-                        self.cast_err(*decl_type, expr, value.view(), "value is wrong type")?;
-                    let value = self.buckets.add(expr);
+    fn check_stmt(
+        &mut self,
+        in_loop: bool,
+        stmt: &Stmt,
+        sym: &mut SymbolTable<'b>,
+        return_type: Option<Type<'b>>,
+        tstmts: &mut Vec<TStmt<'b>>,
+    ) -> Result<(), Error<'b>> {
+        match stmt {
+            Stmt::Pass(_) => {}
+            Stmt::Expr(expr) => {
+                let expr = self.check_expr(sym, expr)?;
+                let expr = self.buckets.add(expr);
+                tstmts.push(TStmt::Expr(expr));
+            }
+            Stmt::Return { ret_val } => {
+                let return_type = unwrap_err(
+                    return_type,
+                    ret_val.view(),
+                    "can't return a value from this context",
+                )?;
 
-                    tstmts.push(TStmt::Assign { to: *name, value });
-                }
-                Stmt::Assign { to, to_view, value } => {
-                    let var_info = unwrap_err(sym.search(*to), *to_view, "name doesn't exist")?;
+                let ret_val_view = ret_val.view();
+                let ret_val = self.check_expr(sym, ret_val)?;
+                let ret_val = self.cast_err(
+                    return_type,
+                    ret_val,
+                    ret_val_view,
+                    "return value is wrong type",
+                )?;
+                let ret_val = self.buckets.add(ret_val);
+
+                tstmts.push(TStmt::Return { ret_val });
+            }
+            Stmt::Declare {
+                name,
+                name_view,
+                type_name,
+                type_view,
+                value,
+            } => {
+                let decl_type =
+                    *unwrap_err(self.types.get(type_name), *type_view, "type doesn't exist")?;
 
-                    let to_type = if let SymbolInfo::Variable { type_, .. } = var_info {
-                        type_
-                    } else {
+                sym.declare(
+                    *name,
+                    SymbolInfo::Variable {
+                        type_: decl_type,
+                        view: *name_view,
+                    },
+                )?;
+
+                let expr = self.check_expr(sym, value)?;
+                let expr = self.cast_err(*decl_type, expr, value.view(), "value is wrong type")?;
+                let value = self.buckets.add(expr);
+
+                tstmts.push(TStmt::Assign { to: *name, value });
+            }
+            Stmt::Assign { to, to_view, value } => {
+                let var_info = match sym.search(*to) {
+                    Some(info) => info,
+                    None => {
+                        // The first assignment to a name declares it, with the
+                        // type of the value being assigned
+                        let expr = self.check_expr(sym, value)?;
+                        if expr.type_() == Type::None {
+                            return err(
+                                value.view(),
+                                "can't infer variable type from None, it needs a type annotation",
+                            );
+                        }
+
+                        let type_ = self.buckets.add(expr.type_());
+                        sym.declare(
+                            *to,
+                            SymbolInfo::Variable {
+                                type_,
+                                view: *to_view,
+                            },
+                        )?;
+
+                        let value = self.buckets.add(expr);
+                        tstmts.push(TStmt::Assign { to: *to, value });
+                        return Ok(());
+                    }
+                };
+
+                let to_type = match var_info {
+                    SymbolInfo::Variable { type_, .. } => type_,
+                    SymbolInfo::Function { .. } => {
                         return err(*to_view, "name being assigned to is a function");
-                    };
+                    }
+                    SymbolInfo::Class { .. } => {
+                        return err(*to_view, "name being assigned to is a class");
+                    }
+                };
 
-                    let expr = self.check_expr(&mut sym, value)?;
-                    let expr =
-                        self.cast_err(*to_type, expr, value.view(), "value is wrong type")?;
-                    let value = self.buckets.add(expr);
+                let expr = self.check_expr(sym, value)?;
+                let expr = self.cast_err(*to_type, expr, value.view(), "value is wrong type")?;
+                let value = self.buckets.add(expr);
 
-                    tstmts.push(TStmt::Assign { to: *to, value });
-                }
-                Stmt::Function {
-                    name,
-                    name_view,
-                    return_type_view,
-                    arguments,
-                    return_type,
-                    stmts,
-                } => {
-                    let (uid, return_type, arg_types) = if let SymbolInfo::Function {
+                tstmts.push(TStmt::Assign { to: *to, value });
+            }
+            Stmt::Function {
+                name,
+                name_view,
+                arguments,
+                stmts,
+                ..
+            } => {
+                // If the signature didn't check out, the function was
+                // never declared and the error has already been reported
+                let (uid, return_type, arg_types) = match sym.symbols.get(name) {
+                    Some(&SymbolInfo::Function {
                         uid,
                         return_type,
                         arguments,
-                        ..
-                    } = sym.search(*name).unwrap()
-                    {
-                        (uid, return_type, arguments)
-                    } else {
-                        panic!()
+                        view,
+                    }) if view == *name_view => (uid, return_type, arguments),
+                    _ => return Ok(()),
+                };
+
+                let arguments: Vec<(&FuncParam, Type<'b>)> =
+                    arguments.iter().zip(arg_types.iter().copied()).collect();
+                let function = self.check_function(
+                    sym,
+                    uid,
+                    *name,
+                    *name_view,
+                    &arguments,
+                    return_type,
+                    stmts,
+                );
+                tstmts.push(function);
+            }
+            Stmt::Class {
+                name,
+                name_view,
+                methods,
+                ..
+            } => {
+                if !sym.is_global() {
+                    return err(
+                        *name_view,
+                        "classes can only be declared at the top level of a module",
+                    );
+                }
+
+                let info = match sym.symbols.get(name) {
+                    Some(&SymbolInfo::Class { info, view }) if view == *name_view => info,
+                    _ => return Ok(()),
+                };
+
+                let mut tmethods = Vec::new();
+                for (method, method_info) in methods.iter().zip(info.methods) {
+                    let (arguments, stmts) = match method {
+                        Stmt::Function {
+                            arguments, stmts, ..
+                        } => (arguments, stmts),
+                        _ => continue,
                     };
 
-                    let mut fsym = symbols_(&sym);
-                    let mut argument_names = Vec::new();
+                    // The object is passed last, below the other arguments,
+                    // so it can be evaluated before them
+                    let mut typed_arguments: Vec<(&FuncParam, Type<'b>)> = arguments[1..]
+                        .iter()
+                        .zip(method_info.arguments.iter().copied())
+                        .collect();
+                    typed_arguments.push((&arguments[0], Type::Class(info.uid)));
 
-                    for (arg, arg_type) in arguments.iter().zip(arg_types) {
-                        argument_names.push(arg.name);
-                        fsym.declare(
-                            arg.name,
+                    let function = self.check_function(
+                        sym,
+                        method_info.uid,
+                        method_info.name,
+                        method.view(),
+                        &typed_arguments,
+                        method_info.return_type,
+                        stmts,
+                    );
+                    tmethods.push(function);
+                }
+
+                tstmts.push(TStmt::Class {
+                    uid: info.uid,
+                    name: *name,
+                    methods: self.buckets.add_array(tmethods),
+                });
+            }
+            Stmt::While {
+                condition,
+                block,
+                else_branch,
+            } => {
+                let condition = self.check_expr(sym, condition)?;
+                let (while_sym, block) = self.check_stmts(true, block, symbols_(sym), return_type);
+                self.errors.extend(while_sym.fold_into_parent());
+                let (else_sym, else_block) =
+                    self.check_stmts(in_loop, else_branch, symbols_(sym), return_type);
+                self.errors.extend(else_sym.fold_into_parent());
+
+                let condition = self.buckets.add(condition);
+                let block = self.buckets.add_array(block);
+                let else_block = self.buckets.add_array(else_block);
+
+                tstmts.push(TStmt::While {
+                    condition,
+                    block,
+                    else_block,
+                });
+            }
+            Stmt::For {
+                target,
+                target_view,
+                iterable,
+                block,
+                else_branch,
+            } => {
+                let (start, stop, step) = self.check_range(sym, iterable)?;
+
+                // Blocks don't get their own scope, so the loop variable
+                // outlives the loop like any other variable assigned in it
+                match sym.search(*target) {
+                    Some(SymbolInfo::Variable { type_, .. }) => {
+                        if *type_ != Type::Int {
+                            return err(*target_view, "loop variable is wrong type");
+                        }
+                    }
+                    Some(SymbolInfo::Function { .. }) => {
+                        return err(*target_view, "name being assigned to is a function");
+                    }
+                    Some(SymbolInfo::Class { .. }) => {
+                        return err(*target_view, "name being assigned to is a class");
+                    }
+                    None => {
+                        let type_ = self.buckets.add(Type::Int);
+                        sym.declare(
+                            *target,
                             SymbolInfo::Variable {
-                                type_: arg_type,
-                                view: arg.view,
+                                type_,
+                                view: *target_view,
                             },
                         )?;
                     }
+                }
 
-                    let (fsym, fblock) =
-                        self.check_stmts(in_loop, stmts, symbols_(&fsym), Some(*return_type))?;
-                    let fdecls = fsym
-                        .symbols
-                        .keys()
-                        .map(|&name| Declaration { name })
-                        .collect();
-                    fsym.fold_into_parent()?;
+                let (for_sym, block) = self.check_stmts(true, block, symbols_(sym), return_type);
+                self.errors.extend(for_sym.fold_into_parent());
+                let (else_sym, else_block) =
+                    self.check_stmts(in_loop, else_branch, symbols_(sym), return_type);
+                self.errors.extend(else_sym.fold_into_parent());
 
-                    let fdecls = self.buckets.add_array(fdecls);
+                tstmts.push(TStmt::ForRange {
+                    var: *target,
+                    start: self.buckets.add(start),
+                    stop: self.buckets.add(stop),
+                    step: self.buckets.add(step),
+                    block: self.buckets.add_array(block),
+                    else_block: self.buckets.add_array(else_block),
+                    view: iterable.view(),
+                });
+            }
+            Stmt::Break(view) => {
+                if !in_loop {
+                    return err(*view, "'break' outside loop");
+                }
+                tstmts.push(TStmt::Break);
+            }
+            Stmt::Continue(view) => {
+                if !in_loop {
+                    return err(*view, "'continue' not properly in loop");
+                }
+                tstmts.push(TStmt::Continue);
+            }
+            Stmt::If {
+                conditioned_blocks,
+                else_branch,
+            } => {
+                let mut sym_tables = Vec::new();
+                let mut ifstmts = Vec::new();
+                for conditioned_block in conditioned_blocks.iter() {
+                    let condition = self.check_expr(sym, &conditioned_block.condition)?;
+                    let block = &conditioned_block.block;
 
-                    let fblock = self.buckets.add_array(fblock);
-                    let argument_names = self.buckets.add_array(argument_names);
+                    let (ifsym, tblock) =
+                        self.check_stmts(in_loop, block, symbols_(sym), return_type);
+                    sym_tables.push(ifsym);
+                    ifstmts.push((condition, tblock));
+                }
 
-                    tstmts.push(TStmt::Function {
-                        uid,
-                        name: *name,
-                        argument_names,
-                        declarations: fdecls,
-                        stmts: fblock,
-                    });
+                let (else_sym, else_block) =
+                    self.check_stmts(in_loop, else_branch, symbols_(sym), return_type);
+
+                sym_tables.push(else_sym);
+
+                // @Performance this could be faster, right now it's quadratic
+                while sym_tables.len() > 1 {
+                    let left = sym_tables.pop().unwrap();
+                    let right = sym_tables.pop().unwrap();
+                    let (merged, errors) = SymbolTable::merge_parallel_tables(left, right);
+                    self.errors.extend(errors);
+                    sym_tables.push(merged);
                 }
-                Stmt::While {
-                    condition,
-                    block,
-                    else_branch,
-                } => {
-                    let condition = self.check_expr(&mut sym, condition)?;
-                    let (while_sym, block) =
-                        self.check_stmts(true, block, symbols_(&sym), return_type)?;
-                    while_sym.fold_into_parent()?;
-                    let (else_sym, else_block) =
-                        self.check_stmts(in_loop, else_branch, symbols_(&sym), return_type)?;
-                    else_sym.fold_into_parent()?;
 
-                    let condition = self.buckets.add(condition);
-                    let block = self.buckets.add_array(block);
-                    let else_block = self.buckets.add_array(else_block);
+                self.errors
+                    .extend(sym_tables.pop().unwrap().fold_into_parent());
 
-                    tstmts.push(TStmt::While {
+                let mut if_false = self.buckets.add_array(else_block);
+                for (condition, block) in ifstmts.into_iter().rev() {
+                    let condition = self.buckets.add(condition);
+                    let if_true = self.buckets.add_array(block);
+                    if_false = self.buckets.add_array(vec![TStmt::If {
                         condition,
-                        block,
-                        else_block,
-                    });
-                }
-                Stmt::Break => {
-                    tstmts.push(TStmt::Break);
+                        if_true,
+                        if_false,
+                    }]);
                 }
-                Stmt::If {
-                    conditioned_blocks,
-                    else_branch,
-                } => {
-                    let mut sym_tables = Vec::new();
-                    let mut ifstmts = Vec::new();
-                    for conditioned_block in conditioned_blocks.iter() {
-                        let condition = self.check_expr(&mut sym, &conditioned_block.condition)?;
-                        let block = &conditioned_block.block;
-
-                        let (ifsym, tblock) =
-                            self.check_stmts(in_loop, block, symbols_(&sym), return_type)?;
-                        sym_tables.push(ifsym);
-                        ifstmts.push((condition, tblock));
-                    }
+                tstmts.push(if_false[0]);
+            }
+            Stmt::AssignMember {
+                to,
+                to_member,
+                to_member_view,
+                value,
+            } => {
+                let object = self.check_expr(sym, to)?;
+                let info = self.class_of(object.type_(), to.view())?;
+                let field = unwrap_err(
+                    info.fields.iter().position(|f| f.name == *to_member),
+                    *to_member_view,
+                    "object has no field with this name",
+                )?;
 
-                    let (else_sym, else_block) =
-                        self.check_stmts(in_loop, else_branch, symbols_(&sym), return_type)?;
+                let type_ = info.fields[field].type_;
+                let expr = self.check_expr(sym, value)?;
+                let expr = self.cast_err(type_, expr, value.view(), "value is wrong type")?;
 
-                    sym_tables.push(else_sym);
+                tstmts.push(TStmt::AssignMember {
+                    object: self.buckets.add(object),
+                    field: field as u32,
+                    name: *to_member,
+                    value: self.buckets.add(expr),
+                    view: joinr(to.view(), *to_member_view),
+                });
+            }
+        }
 
-                    // @Performance this could be faster, right now it's quadratic
-                    while sym_tables.len() > 1 {
-                        let left = sym_tables.pop().unwrap();
-                        let right = sym_tables.pop().unwrap();
-                        sym_tables.push(SymbolTable::merge_parallel_tables(left, right)?);
-                    }
+        return Ok(());
+    }
 
-                    sym_tables.pop().unwrap().fold_into_parent()?;
-
-                    let mut if_false = self.buckets.add_array(else_block);
-                    for (condition, block) in ifstmts.into_iter().rev() {
-                        let condition = self.buckets.add(condition);
-                        let if_true = self.buckets.add_array(block);
-                        if_false = self.buckets.add_array(vec![TStmt::If {
-                            condition,
-                            if_true,
-                            if_false,
-                        }]);
-                    }
-                    tstmts.push(if_false[0]);
-                }
-                _ => panic!(),
+    // Checks the body of a function whose signature has already been declared,
+    // with `arguments` in the order the function takes them
+    #[allow(clippy::too_many_arguments)]
+    fn check_function(
+        &mut self,
+        sym: &SymbolTable<'b>,
+        uid: u32,
+        name: u32,
+        name_view: CRange,
+        arguments: &[(&FuncParam, Type<'b>)],
+        return_type: &'b Type<'b>,
+        stmts: &[Stmt],
+    ) -> TStmt<'b> {
+        let mut fsym = symbols_(sym);
+        let mut argument_names = Vec::new();
+
+        for &(arg, arg_type) in arguments {
+            argument_names.push(arg.name);
+            let declared = fsym.declare(
+                arg.name,
+                SymbolInfo::Variable {
+                    type_: self.buckets.add(arg_type),
+                    view: arg.view,
+                },
+            );
+            if let Err(e) = declared {
+                self.errors.push(e);
             }
         }
 
-        return Ok((sym, tstmts));
+        // Loops around a function don't extend into its body
+        let (fsym, mut fblock) =
+            self.check_stmts(false, stmts, symbols_(&fsym), Some(*return_type));
+
+        // Falling off the end returns None, so it's an error for
+        // anything else, and traps if it happens anyway
+        let returns_none = matches!(return_type, Type::None | Type::Any);
+        if !returns_none && !flow::block_exits(&fblock, true) {
+            fblock.push(TStmt::Trap {
+                error: self.first_error + self.errors.len() as u32,
+            });
+            self.errors.push(Error {
+                location: name_view,
+                message: "function can reach its end without returning a value",
+                code: Code::Type,
+            });
+        }
+        let fdecls = declarations(&fsym.symbols);
+        self.errors.extend(fsym.fold_into_parent());
+
+        let fdecls = self.buckets.add_array(fdecls);
+
+        let fblock = self.buckets.add_array(fblock);
+        let argument_names = self.buckets.add_array(argument_names);
+
+        return TStmt::Function {
+            uid,
+            name,
+            argument_names,
+            declarations: fdecls,
+            stmts: fblock,
+        };
+    }
+
+    // Only calls to the builtin `range` can be looped over so far. Returns its
+    // start, stop and step, filling in the ones that were left out.
+    fn check_range(
+        &mut self,
+        sym: &mut SymbolTable<'b>,
+        iterable: &Expr,
+    ) -> Result<(TExpr<'b>, TExpr<'b>, TExpr<'b>), Error<'b>> {
+        let (arguments, arguments_view) = match iterable {
+            Expr::Call {
+                callee: RANGE_IDX,
+                arguments,
+                arguments_view,
+                ..
+            } if sym.search(RANGE_IDX).is_none() => (arguments, arguments_view),
+            _ => return err(iterable.view(), "only range() can be looped over"),
+        };
+
+        let mut values = Vec::new();
+        for argument in arguments.iter() {
+            let value = self.check_expr(sym, argument)?;
+            let value = self.cast_err(
+                Type::Int,
+                value,
+                argument.view(),
+                "range() arguments need to be ints",
+            )?;
+            values.push(value);
+        }
+
+        return match values[..] {
+            [stop] => Ok((TExpr::Int(0), stop, TExpr::Int(1))),
+            [start, stop] => Ok((start, stop, TExpr::Int(1))),
+            [start, stop, step] => Ok((start, stop, step)),
+            _ => err(*arguments_view, "range() takes 1 to 3 arguments"),
+        };
     }
 
     fn check_expr(
@@ -446,18 +1093,25 @@ where
     ) -> Result<TExpr<'b>, Error<'b>> {
         match expr {
             Expr::Int { value, view } => {
+                if *value > INT_MAX as u64 {
+                    return err(*view, "integer literal is out of range for an int");
+                }
                 return Ok(TExpr::Int(*value as i64));
             }
-            Expr::Float { value, view } => {
+            Expr::Float { value, .. } => {
                 return Ok(TExpr::Float(*value));
             }
-            Expr::None(view) => {
+            Expr::StringLiteral { value, .. } => {
+                let value = self.buckets.add_str(value);
+                return Ok(TExpr::Str(value));
+            }
+            Expr::None(_) => {
                 return Ok(TExpr::None);
             }
-            Expr::False(view) => {
+            Expr::False(_) => {
                 return Ok(TExpr::Bool(false));
             }
-            Expr::True(view) => {
+            Expr::True(_) => {
                 return Ok(TExpr::Bool(true));
             }
             Expr::Ident { id, view } => {
@@ -465,42 +1119,160 @@ where
 
                 let type_ = match var_info {
                     SymbolInfo::Variable { type_, .. } => *type_,
-                    SymbolInfo::Function { .. } => panic!("we don't have function objects yet"),
+                    SymbolInfo::Function { .. } => {
+                        return err(*view, "functions can't be used as values yet");
+                    }
+                    SymbolInfo::Class { .. } => {
+                        return err(*view, "classes can't be used as values yet");
+                    }
                 };
 
                 return Ok(TExpr::Ident { id: *id, type_ });
             }
-            Expr::Minus { left, right, view } => {
-                let left = self.check_expr(sym, left)?;
-                let right = self.check_expr(sym, right)?;
+            Expr::Binary {
+                op,
+                left,
+                right,
+                view,
+            } => {
+                let (left_view, right_view) = (left.view(), right.view());
+                let mut left = self.check_expr(sym, left)?;
+                let mut right = self.check_expr(sym, right)?;
+                if !matches!(op, BinOp::And | BinOp::Or) {
+                    match (left.type_(), right.type_()) {
+                        (Type::Int, Type::Float) => {
+                            left = self.widen(left, left_view);
+                        }
+                        (Type::Float, Type::Int) => {
+                            right = self.widen(right, right_view);
+                        }
+                        _ => {}
+                    }
+                }
                 let left = self.buckets.add(left);
                 let right = self.buckets.add(right);
 
-                if left.type_() == right.type_() {
-                    return Ok(TExpr::Minus {
-                        left,
-                        right,
-                        type_: left.type_(),
-                    });
-                } else {
-                    return err(*view, "incompatible types for addition");
-                }
+                let (ltype, rtype) = (left.type_(), right.type_());
+                let type_ = match op {
+                    BinOp::And | BinOp::Or => {
+                        if ltype != rtype {
+                            return err(*view, "operands of 'and'/'or' need to be the same type");
+                        }
+                        ltype
+                    }
+                    _ if ltype != rtype => {
+                        return err(*view, "incompatible types for arithmetic operation");
+                    }
+                    BinOp::Add if ltype == Type::Str => Type::Str,
+                    _ if !ltype.is_primitive() => {
+                        return err(*view, "arithmetic operands need to be numbers");
+                    }
+                    BinOp::Div => Type::Float,
+                    _ => ltype,
+                };
+
+                return Ok(TExpr::Binary {
+                    op: *op,
+                    left,
+                    right,
+                    type_,
+                    view: *view,
+                });
             }
-            Expr::Add { left, right, view } => {
-                let left = self.check_expr(sym, left)?;
-                let right = self.check_expr(sym, right)?;
-                let left = self.buckets.add(left);
-                let right = self.buckets.add(right);
+            // Negative literals are folded, which is also the only way to
+            // write INT_MIN
+            Expr::Unary {
+                op: UnaryOp::Neg,
+                operand: Expr::Int { value, .. },
+                ..
+            } if *value <= INT_MIN.unsigned_abs() => {
+                return Ok(TExpr::Int((*value as i64).wrapping_neg()));
+            }
+            Expr::Unary {
+                op: UnaryOp::Neg,
+                operand: Expr::Float { value, .. },
+                ..
+            } => {
+                return Ok(TExpr::Float(-value));
+            }
+            Expr::Unary { op, operand, view } => {
+                let operand = self.check_expr(sym, operand)?;
+                let operand = self.buckets.add(operand);
 
-                if left.type_() == right.type_() {
-                    return Ok(TExpr::Add {
-                        left,
+                let type_ = match op {
+                    UnaryOp::Not => Type::Bool,
+                    UnaryOp::Neg | UnaryOp::Pos => {
+                        if !operand.type_().is_primitive() {
+                            return err(*view, "operand of unary sign needs to be a number");
+                        }
+                        operand.type_()
+                    }
+                };
+
+                return Ok(TExpr::Unary {
+                    op: *op,
+                    operand,
+                    type_,
+                    view: *view,
+                });
+            }
+            Expr::Compare { first, rest, view } => {
+                let mut operands = vec![(self.check_expr(sym, first)?, first.view())];
+                for comparison in rest.iter() {
+                    let right = self.check_expr(sym, &comparison.right)?;
+                    operands.push((right, comparison.right.view()));
+                }
+
+                // Ints in a chain that also has floats are compared as floats,
+                // so an operand between the two has the same type on both sides
+                let has_type = |t| operands.iter().any(|(operand, _)| operand.type_() == t);
+                if has_type(Type::Int) && has_type(Type::Float) {
+                    for (operand, view) in operands.iter_mut() {
+                        if operand.type_() == Type::Int {
+                            *operand = self.widen(*operand, *view);
+                        }
+                    }
+                }
+
+                let mut operands = operands.into_iter();
+                let (first, mut prev_view) = operands.next().unwrap();
+                let mut prev_type = first.type_();
+                let mut trest = Vec::new();
+                for (comparison, (right, _)) in rest.iter().zip(operands) {
+                    let rtype = right.type_();
+                    let comparable = match comparison.op {
+                        CmpOp::Eq | CmpOp::Ne => {
+                            prev_type == rtype
+                                || prev_type == Type::None
+                                || rtype == Type::None
+                                || (is_object(prev_type) && is_object(rtype))
+                        }
+                        _ => match prev_type {
+                            Type::None | Type::Class(_) | Type::Interface(_) => false,
+                            _ => prev_type == rtype,
+                        },
+                    };
+
+                    if !comparable {
+                        return err(
+                            joinr(prev_view, comparison.right.view()),
+                            "values of these types can't be compared",
+                        );
+                    }
+
+                    prev_view = comparison.right.view();
+                    prev_type = rtype;
+                    trest.push(TComparison {
+                        op: comparison.op,
                         right,
-                        type_: left.type_(),
                     });
-                } else {
-                    return err(*view, "incompatible types for addition");
                 }
+
+                return Ok(TExpr::Compare {
+                    first: self.buckets.add(first),
+                    rest: self.buckets.add_array(trest),
+                    view: *view,
+                });
             }
             Expr::Call {
                 callee,
@@ -514,40 +1286,165 @@ where
                     "name being called doesn't exist",
                 )?;
 
-                if let SymbolInfo::Function {
-                    uid,
-                    return_type,
-                    arguments: args_formal,
-                    ..
-                } = var_info
-                {
-                    let mut args = Vec::new();
-                    if args_formal.len() != arguments.len() {
-                        return err(*arguments_view, "wrong number of arguments");
+                match var_info {
+                    SymbolInfo::Function {
+                        uid,
+                        return_type,
+                        arguments: args_formal,
+                        ..
+                    } => {
+                        let arguments =
+                            self.check_arguments(sym, args_formal, arguments, *arguments_view)?;
+                        return Ok(TExpr::Call {
+                            callee_uid: uid,
+                            arguments,
+                            type_: *return_type,
+                            view: expr.view(),
+                        });
                     }
+                    // Calling a class creates an instance, and passes the
+                    // arguments on to its `__init__`
+                    SymbolInfo::Class { info, .. } => {
+                        let init = info.methods.iter().find(|m| m.name == INIT_IDX);
+                        let args_formal = match init {
+                            Some(init) => init.arguments,
+                            None => &[],
+                        };
+                        let arguments =
+                            self.check_arguments(sym, args_formal, arguments, *arguments_view)?;
 
-                    for (formal, arg) in args_formal.iter().zip(arguments.iter()) {
-                        let view = arg.view();
-                        let arg = self.check_expr(sym, arg)?;
-                        let arg = self.cast_err(*formal, arg, view, "argument is wrong type")?;
-                        args.push(arg);
+                        let fields: Vec<TExpr> = info.fields.iter().map(|f| f.default).collect();
+                        return Ok(TExpr::New {
+                            class: info.uid,
+                            name: *callee,
+                            fields: self.buckets.add_array(fields),
+                            init: init.map(|init| init.uid),
+                            arguments,
+                            view: expr.view(),
+                        });
+                    }
+                    SymbolInfo::Variable { .. } => {
+                        return err(expr.view(), "callee not a function");
                     }
+                }
+            }
+            Expr::DotAccess {
+                parent,
+                member_id,
+                member_view,
+            } => {
+                let object = self.check_expr(sym, parent)?;
+                let info = self.class_of(object.type_(), parent.view())?;
+                let field = match info.fields.iter().position(|f| f.name == *member_id) {
+                    Some(field) => field,
+                    None if info.methods.iter().any(|m| m.name == *member_id) => {
+                        return err(*member_view, "methods can't be used as values yet");
+                    }
+                    None => return err(*member_view, "object has no member with this name"),
+                };
 
-                    return Ok(TExpr::Call {
-                        callee_uid: uid,
-                        arguments: self.buckets.add_array(args),
-                        type_: *return_type,
+                return Ok(TExpr::Member {
+                    object: self.buckets.add(object),
+                    field: field as u32,
+                    name: *member_id,
+                    type_: info.fields[field].type_,
+                    view: expr.view(),
+                });
+            }
+            Expr::MethodCall {
+                parent,
+                method,
+                method_view,
+                arguments,
+                arguments_view,
+            } => {
+                let object = self.check_expr(sym, parent)?;
+                if let Type::Interface(interface) = object.type_() {
+                    let idx = unwrap_err(
+                        interface.iter().position(|m| m.name == *method),
+                        *method_view,
+                        "object has no method with this name",
+                    )?;
+                    let arguments = self.check_arguments(
+                        sym,
+                        interface[idx].arguments,
+                        arguments,
+                        *arguments_view,
+                    )?;
+                    return Ok(TExpr::InterfaceCall {
+                        object: self.buckets.add(object),
+                        interface,
+                        method: idx as u32,
+                        arguments,
+                        view: expr.view(),
                     });
-                } else {
-                    return err(expr.view(), "callee not a function");
                 }
+
+                let info = self.class_of(object.type_(), parent.view())?;
+                let idx = unwrap_err(
+                    info.methods.iter().position(|m| m.name == *method),
+                    *method_view,
+                    "object has no method with this name",
+                )?;
+
+                let method_info = info.methods[idx];
+                let arguments =
+                    self.check_arguments(sym, method_info.arguments, arguments, *arguments_view)?;
+                return Ok(TExpr::MethodCall {
+                    object: self.buckets.add(object),
+                    method: idx as u32,
+                    name: *method,
+                    arguments,
+                    type_: *method_info.return_type,
+                    view: expr.view(),
+                });
             }
-            x => {
+            _ => {
                 return err(expr.view(), "not implemented yet");
             }
         }
     }
 
+    fn check_arguments(
+        &mut self,
+        sym: &mut SymbolTable<'b>,
+        args_formal: &[Type<'b>],
+        arguments: &[Expr],
+        arguments_view: CRange,
+    ) -> Result<&'b [TExpr<'b>], Error<'b>> {
+        if args_formal.len() != arguments.len() {
+            return err(arguments_view, "wrong number of arguments");
+        }
+
+        let mut args = Vec::new();
+        for (formal, arg) in args_formal.iter().zip(arguments.iter()) {
+            let view = arg.view();
+            let arg = self.check_expr(sym, arg)?;
+            let arg = self.cast_err(*formal, arg, view, "argument is wrong type")?;
+            args.push(arg);
+        }
+        return Ok(self.buckets.add_array(args));
+    }
+
+    // Converts an int to a float, for assignments and for arithmetic that
+    // mixes the two
+    fn widen(&mut self, value: TExpr<'b>, view: CRange) -> TExpr<'b> {
+        self.warn(
+            view,
+            Code::ImplicitCast,
+            "int implicitly converted to float",
+        );
+        if let TExpr::Int(value) = value {
+            return TExpr::Float(value as f64);
+        }
+
+        let arguments = vec![TExpr::Int(FLOAT_CAST as i64), value];
+        return TExpr::ECall {
+            arguments: self.buckets.add_array(arguments),
+            type_: Type::Float,
+        };
+    }
+
     fn cast_err(
         &mut self,
         to: Type<'b>,
@@ -555,25 +1452,45 @@ where
         view: CRange,
         err_msg: &'b str,
     ) -> Result<TExpr<'b>, Error<'b>> {
-        match value.type_() {
-            Type::None => return Ok(value),
-            _ => {}
+        if value.type_() == Type::None {
+            return Ok(value);
         }
 
         return match to {
             Type::Any => Ok(value),
             Type::None => err(view, err_msg),
-            Type::Float => {
-                if value.type_() == Type::Float {
-                    Ok(value)
-                } else if value.type_() == Type::Int {
-                    err(view, err_msg) // TODO implement casting
-                } else {
-                    err(view, err_msg)
-                }
-            }
+            // Ints are converted implicitly, like the README says, but it's
+            // worth a warning
+            Type::Float if value.type_() == Type::Int => Ok(self.widen(value, view)),
             x if x == value.type_() => Ok(value),
-            x => err(view, err_msg),
+            Type::Interface(interface) => match value.type_() {
+                Type::Class(uid) if self.implements(uid, interface) => Ok(TExpr::Upcast {
+                    value: self.buckets.add(value),
+                    interface,
+                }),
+                Type::Interface(from) if self.extends(from, interface) => Ok(TExpr::Upcast {
+                    value: self.buckets.add(value),
+                    interface,
+                }),
+                Type::Class(_) | Type::Interface(_) => {
+                    err(view, "object doesn't have the methods that are used on it")
+                }
+                _ => err(view, err_msg),
+            },
+            _ => err(view, err_msg),
         };
     }
 }
+
+// Objects can be compared with each other whatever their types, since an
+// interface can hold an object of any class
+fn is_object(type_: Type) -> bool {
+    return matches!(type_, Type::Class(_) | Type::Interface(_));
+}
+
+// Sorted by name, so that frame layouts don't depend on hash order
+fn declarations(symbols: &HashMap<u32, SymbolInfo>) -> Vec<Declaration> {
+    let mut names: Vec<u32> = symbols.keys().copied().collect();
+    names.sort();
+    return names.into_iter().map(|name| Declaration { name }).collect();
+}
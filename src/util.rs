@@ -6,7 +6,7 @@ use std::marker::PhantomData;
 use std::mem::size_of;
 use std::ops::Range;
 use std::ptr;
-use std::slice::{from_raw_parts, from_raw_parts_mut};
+use std::slice::from_raw_parts_mut;
 use std::str::from_utf8_unchecked_mut;
 
 const BUCKET_SIZE: usize = 1024 * 1024;
@@ -49,16 +49,76 @@ impl std::fmt::Display for CRange {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+// What a diagnostic is about. The names are stable, since pragmas and tools
+// refer to them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Code {
+    Syntax,
+    Type,
+    Inference,
+    Runtime,
+    ImplicitCast,
+    UnreachableCode,
+}
+
+impl Code {
+    pub const ALL: [Code; 6] = [
+        Code::Syntax,
+        Code::Type,
+        Code::Inference,
+        Code::Runtime,
+        Code::ImplicitCast,
+        Code::UnreachableCode,
+    ];
+
+    pub fn name(self) -> &'static str {
+        return match self {
+            Code::Syntax => "syntax-error",
+            Code::Type => "type-error",
+            Code::Inference => "inference-error",
+            Code::Runtime => "runtime-error",
+            Code::ImplicitCast => "implicit-cast",
+            Code::UnreachableCode => "unreachable-code",
+        };
+    }
+
+    pub fn from_name(name: &str) -> Option<Code> {
+        return Code::ALL.iter().copied().find(|code| code.name() == name);
+    }
+
+    pub fn severity(self) -> Severity {
+        return match self {
+            Code::ImplicitCast | Code::UnreachableCode => Severity::Warning,
+            _ => Severity::Error,
+        };
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
 pub struct Error<'a> {
     pub location: CRange,
     pub message: &'a str,
+    pub code: Code,
+}
+
+impl<'a> Error<'a> {
+    pub fn severity(&self) -> Severity {
+        return self.code.severity();
+    }
 }
 
+// Most errors are type errors; the parser marks its own as syntax errors
 pub fn err<'a, T>(loc: CRange, msg: &'a str) -> Result<T, Error<'a>> {
     return Err(Error {
         location: loc,
         message: msg,
+        code: Code::Type,
     });
 }
 
@@ -70,16 +130,31 @@ pub fn unwrap_err<'a, T>(
     if let Some(val) = value {
         return Ok(val);
     } else {
-        return Err(Error { location, message });
+        return Err(Error {
+            location,
+            message,
+            code: Code::Type,
+        });
     }
 }
 
-pub fn mut_ref_to_slice<T>(data: &mut T) -> &mut [T] {
-    return unsafe { from_raw_parts_mut(data, 1) };
+// Regions of source where diagnostics with a given code were turned off by
+// pragmas
+#[derive(Debug, Clone, Default)]
+pub struct Silenced {
+    regions: Vec<(Code, CRange)>,
 }
 
-pub fn ref_to_slice<T>(data: &T) -> &[T] {
-    return unsafe { from_raw_parts(data, 1) };
+impl Silenced {
+    pub fn add(&mut self, code: Code, region: CRange) {
+        self.regions.push((code, region));
+    }
+
+    pub fn contains(&self, code: Code, location: CRange) -> bool {
+        return self.regions.iter().any(|&(silenced, region)| {
+            silenced == code && region.start <= location.start && location.start < region.end
+        });
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -102,6 +177,7 @@ impl<'a> Buckets<'a> {
         };
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn drop(&mut self) {
         for bucket in &self.buckets {
             unsafe {
@@ -115,7 +191,7 @@ impl<'a> Buckets<'a> {
         }
     }
 
-    pub unsafe fn new_unsafe(&mut self, size: usize) -> *mut u8 {
+    unsafe fn new_unsafe(&mut self, size: usize) -> *mut u8 {
         // @Correctness panics in debug mode without this check
         let size = if size != 0 {
             (size - 1) / 16 * 16 + 16
@@ -123,7 +199,7 @@ impl<'a> Buckets<'a> {
             size
         };
         if size > BUCKET_SIZE {
-            let bucket = self.buckets.last().unwrap().clone();
+            let bucket = *self.buckets.last().unwrap();
             let begin = alloc(Layout::from_size_align_unchecked(size, 8));
             *self.buckets.last_mut().unwrap() = Bucket {
                 begin,
@@ -185,6 +261,20 @@ impl<'a> Buckets<'a> {
     }
 }
 
+impl<'a> Default for Buckets<'a> {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+// 1-based line and column of a byte offset
+pub fn line_col(input: &str, offset: u32) -> (usize, usize) {
+    let before = &input[..offset as usize];
+    let line = before.matches('\n').count() + 1;
+    let col = before.len() - before.rfind('\n').map(|idx| idx + 1).unwrap_or(0) + 1;
+    return (line, col);
+}
+
 pub struct StringWriter {
     buf: Vec<u8>,
 }
@@ -196,12 +286,14 @@ impl StringWriter {
         }
     }
 
-    pub fn to_string(self) -> String {
-        if let Ok(s) = String::from_utf8(self.buf) {
-            s
-        } else {
-            String::new()
-        }
+    pub fn into_string(self) -> String {
+        return String::from_utf8(self.buf).unwrap_or_default();
+    }
+}
+
+impl Default for StringWriter {
+    fn default() -> Self {
+        return Self::new();
     }
 }
 
@@ -226,6 +318,12 @@ impl Void {
     }
 }
 
+impl Default for Void {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
 impl Write for Void {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
         Ok(buf.len())
@@ -316,7 +414,7 @@ fn unescape_octal_leading(c: char, queue: &VecDeque<char>) -> Option<char> {
 
     let mut s = String::new();
     s.push(c);
-    s.push(*try_option!(queue.get(0)));
+    s.push(*try_option!(queue.front()));
     s.push(*try_option!(queue.get(1)));
 
     let u = try_option!(u32::from_str_radix(&s, 8).ok());
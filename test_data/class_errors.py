@@ -0,0 +1,57 @@
+class Box:
+    value: int = 1
+    value: str
+    def get(self) -> int:
+        return self.value
+
+
+class Pair:
+    first: int = 0
+    second: Missing
+
+class Shape:
+    sides: int = 3
+    name: str = "tri" + "angle"
+
+class Square:
+    def __init__(self) -> int:
+        return 1
+
+class Circle:
+    def area():
+        return 0.0
+
+class Line:
+    def length(self: Shape) -> float:
+        return 0.0
+
+class int:
+    pass
+
+class Empty:
+    pass
+
+class Thing:
+    size: float = 2
+    def grow(self, by: float) -> float:
+        self.size = self.size + by
+        return self.size
+    def describe(self, other: Empty):
+        pass
+
+t = Thing()
+t.grow("big")
+t.grow(1, 2)
+t.weight = 3
+print(t.weight)
+print(t.shrink())
+x = t.grow
+print(t < t)
+e = Empty()
+e.describe(1)
+Thing = 4
+n = 5
+print(n.real)
+if True:
+    class Nested:
+        pass
@@ -0,0 +1,55 @@
+class Point:
+    x: int = 0
+    y: int = 0
+
+    def __init__(self, x: int, y: int):
+        self.x = x
+        self.y = y
+
+    def length_squared(self) -> int:
+        return self.x * self.x + self.y * self.y
+
+    def moved(self, dx, dy):
+        return Point(self.x + dx, self.y + dy)
+
+
+class Counter:
+    count: int
+    label: str = "counter"
+
+    def increment(self):
+        if self.count == None:
+            self.count = 0
+        self.count = self.count + 1
+        return self.count
+
+
+p = Point(3, 4)
+print(p.x)
+print(p.length_squared())
+q = p.moved(1, -1)
+print(q.x)
+print(q.y)
+p.x = 10
+print(p.length_squared())
+print(p == p)
+print(p == q)
+
+c = Counter()
+print(c.count)
+c.increment()
+print(c.increment())
+print(c.label)
+print(c)
+
+
+def make_points(n: int) -> Point:
+    last = Point(0, 0)
+    for i in range(n):
+        last = last.moved(i, 1)
+    return last
+
+
+far = make_points(100000)
+print(far.x)
+print(far.y)
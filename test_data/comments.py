@@ -0,0 +1,11 @@
+# Comments run from a `#` to the end of the line
+x: int = 1  # after code
+def inc(a: int) -> int:
+# comments don't count towards indentation
+    return a + 1  # print(a)
+        # even when they're indented differently
+print(inc(x))
+print(inc(  # inside brackets
+    x))
+#
+print("# isn't a comment inside a string")
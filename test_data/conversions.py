@@ -0,0 +1,41 @@
+print(int(3.7))
+print(int(-3.7))
+print(float(2))
+print(bool(0))
+print(bool("x"))
+print(str(1.5) + "!")
+print(int("12") + 1)
+print(int(" -1_000 "))
+print(float(" 2.5e1 "))
+print(int(True))
+print(bool(0.0))
+print(bool(""))
+print(str(True) + str(None))
+print(str(7) == "7")
+print(float("inf") > 1000000.0)
+count: int = int(float("1e3"))
+print(count)
+print(bool(None))
+print(not None)
+if None:
+    print("None is truthy")
+else:
+    print("None is falsy")
+nothing: str = None
+while nothing:
+    nothing = None
+print(nothing or "default")
+print(str(1e20))
+print(str(1e-7))
+print(str(float("nan")))
+print(str(float("inf")))
+print(str(float("-inf")))
+print(1e16)
+print(1e15)
+print(123456789012345678.0)
+print(0.0001)
+print(0.00001234)
+print(1.5e-10)
+print(0.1 + 0.2)
+print(0.0 - 0.0)
+print(-2.5)
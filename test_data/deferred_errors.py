@@ -0,0 +1,15 @@
+def broken(a: int) -> int:
+    b: int = "not an int"
+    return a
+
+
+def fine(a: int) -> int:
+    return a + 1
+
+
+print(fine(1))
+if fine(0) == 2:
+    print(broken(1))
+print("still running")
+print(broken(2))
+print("unreachable")
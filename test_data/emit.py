@@ -0,0 +1,7 @@
+def half(a: int) -> float:
+    return a / 2
+
+x: int = 1 + 2 * 3
+while x > 0 and not x == 4:
+    x = x - 1
+print(half(-x))
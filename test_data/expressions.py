@@ -16,6 +16,7 @@ def add(a1: float, a2: float, a3: float) -> float:
 def hi(asdf: float) -> float:
     print(asdf)
     print(asdf)
+    return asdf
 
 
 hi(12.0)
@@ -0,0 +1,9 @@
+for a in 5:
+    print(a)
+for b in range():
+    print(b)
+for c in range(1.5):
+    print(c)
+d = "text"
+for d in range(3):
+    print(d)
@@ -0,0 +1,36 @@
+for i in range(3):
+    print(i)
+print(i)
+total = 0
+for j in range(10, 0, -3):
+    if j == 7:
+        continue
+    total = total + j
+else:
+    print("no break")
+print(total)
+def find(n: int) -> int:
+    for k in range(2, n):
+        if n % k == 0:
+            return k
+    return n
+print(find(35))
+for a in range(5):
+    for b in range(5):
+        if b == 2:
+            break
+        print(a * 10 + b)
+    if a == 1:
+        break
+else:
+    print("unreachable")
+n = 0
+while n < 5:
+    n = n + 1
+    if n % 2 == 0:
+        continue
+    print(n)
+for z in range(0):
+    print("never")
+else:
+    print("empty")
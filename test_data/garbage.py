@@ -0,0 +1,13 @@
+def step(total: int, count: int) -> int:
+    label = "step"
+    return total + count
+
+
+count = 0
+total = 0
+while count < 200000:
+    count = count + 1
+    total = step(total, count)
+
+print(count)
+print(total)
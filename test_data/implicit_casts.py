@@ -0,0 +1,18 @@
+def half(x: float) -> float:
+    return x / 2.0
+
+
+def widen(n: int) -> float:
+    return n
+
+
+total: float = 1
+count: int = 3
+total = count
+print(total)
+print(half(5))
+print(widen(count + 1))
+print(1 + 2.0)
+print(count * 0.5)
+print(1 == 1.0)
+print(1 < 1.5 < count)
@@ -0,0 +1,27 @@
+count = 0
+total = 0.0
+name = "lars"
+done = False
+
+while count < 3:
+    count = count + 1
+    total = total + 1.5
+
+print(count)
+print(total)
+print(name + "!")
+print(not done)
+
+if count == 3:
+    label = "three"
+else:
+    label = "other"
+print(label)
+
+
+def scale(x: float) -> float:
+    factor = 2.0
+    return x * factor
+
+
+print(scale(total))
@@ -0,0 +1,3 @@
+a = 1125899906842624
+b = -1125899906842625
+c = 99999999999999999999999
@@ -0,0 +1,3 @@
+big = 1125899906842623
+print(big)
+print(big + 1)
@@ -0,0 +1,38 @@
+class Cat:
+    def speak(self) -> str:
+        return "meow"
+
+
+class Dog:
+    def speak(self) -> str:
+        return "woof"
+
+    def fetch(self) -> int:
+        return 1
+
+
+class Robot:
+    def speak(self) -> int:
+        return 0
+
+
+def play(pet):
+    print(pet.speak())
+    print(pet.fetch())
+
+
+def talk(speaker):
+    print(speaker.speak())
+
+
+def inspect(thing):
+    thing.speak()
+    return thing.legs
+
+
+play(Dog())
+play(Cat())
+talk(Cat())
+talk(Robot())
+inspect(Cat())
+inspect(Dog())
@@ -0,0 +1,39 @@
+class Dog:
+    def speak(self) -> str:
+        return "woof"
+
+    def run(self) -> str:
+        return "dog runs"
+
+    def sleep(self) -> str:
+        return "dog sleeps"
+
+
+class Cat:
+    def sleep(self) -> str:
+        return "cat sleeps"
+
+    def speak(self) -> str:
+        return "meow"
+
+    def run(self) -> str:
+        return "cat runs"
+
+
+def talk(p):
+    print(p.speak())
+
+
+def play(pet):
+    talk(pet)
+    print(pet.run())
+
+
+def visit(animal):
+    play(animal)
+    print(animal.sleep())
+
+
+visit(Dog())
+visit(Cat())
+play(Cat())
@@ -0,0 +1,53 @@
+class Square:
+    side: float = 1.0
+
+    def __init__(self, side: float):
+        self.side = side
+
+    def area(self) -> float:
+        return self.side * self.side
+
+    def name(self) -> str:
+        return "square"
+
+
+class Circle:
+    radius: float = 1.0
+
+    def __init__(self, radius: float):
+        self.radius = radius
+
+    def area(self) -> float:
+        return 3.0 * self.radius * self.radius
+
+    def name(self) -> str:
+        return "circle"
+
+    def grow(self, by: float):
+        self.radius = self.radius + by
+
+
+def describe(shape):
+    return shape.name() + " " + str(shape.area())
+
+
+def bigger(a, b):
+    if a.area() > b.area():
+        return a
+    return b
+
+
+def total(first, second) -> float:
+    return first.area() + second.area()
+
+
+sq = Square(2.0)
+ci = Circle(1.0)
+print(describe(sq))
+print(describe(ci))
+print(bigger(sq, ci).area())
+ci.grow(1.0)
+print(bigger(sq, ci).area())
+print(total(sq, ci))
+print(total(ci, ci))
+print(bigger(ci, ci) == ci)
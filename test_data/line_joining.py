@@ -0,0 +1,17 @@
+def add(a: int,
+        b: int,  # comments are fine in here too
+        c: int) -> int:
+   # three spaces of indentation is as good as four
+   return a + \
+          b + c
+
+
+total = add(
+    1,
+
+    2,
+  3)
+print(total)
+if total == 6 and \
+        True:
+   print("joined")
@@ -0,0 +1,12 @@
+break
+while True:
+    def inner():
+        continue
+    break
+if True:
+    continue
+for i in range(3):
+    if i == 1:
+        break
+else:
+    break
@@ -0,0 +1,58 @@
+def sign(n: int) -> int:
+    if n < 0:
+        return -1
+    elif n > 0:
+        return 1
+
+
+def firsteven(limit: int) -> int:
+    for i in range(limit):
+        if i % 2 == 0:
+            return i
+    else:
+        return -1
+
+
+def forever() -> int:
+    while True:
+        pass
+
+
+def search(n: int) -> int:
+    while n > 0:
+        if n == 3:
+            break
+        n = n - 1
+    else:
+        return 0
+
+
+def early(n: int) -> int:
+    return n
+    print("never")
+    print("not reported twice")
+
+
+def looptail():
+    for i in range(3):
+        continue
+        print(i)
+    while True:
+        break
+        print("also never")
+
+
+def trapped(n: int) -> int:
+    if n > 0:
+        print(undefined)
+    else:
+        return n
+    return 0
+
+
+def broken() -> int:
+    print(undefined)
+
+
+print(sign(5))
+print(sign(0))
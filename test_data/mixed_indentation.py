@@ -0,0 +1,6 @@
+def f(x: int) -> int:
+    y = x + 1
+	return y
+
+
+print(f(1))
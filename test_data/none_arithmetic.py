@@ -0,0 +1,9 @@
+def missing() -> int:
+    return None
+
+
+print(missing() == None)
+x: int = missing()
+y: float = None
+print(y == None)
+print(x + 1)
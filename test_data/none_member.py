@@ -0,0 +1,16 @@
+class Node:
+    value: int = 1
+    next: Node
+
+    def last(self) -> Node:
+        node = self
+        while node.next != None:
+            node = node.next
+        return node
+
+
+head = Node()
+head.next = Node()
+head.next.value = 2
+print(head.last().value)
+print(head.next.next.value)
@@ -0,0 +1,5 @@
+d = 1__000
+e = 0x
+f = 12abc
+g = 1e
+h = 007
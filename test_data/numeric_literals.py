@@ -0,0 +1,13 @@
+print(0xff)
+print(0o17 + 0b101)
+print(1_000_000)
+print(1e3)
+print(.5)
+print(1.)
+print(2.5e-3)
+print(1_0.2_5E+0_1)
+print(0x_DEAD_beef)
+print(-1125899906842624)
+print(1125899906842623)
+print(-1.5)
+print(000)
@@ -0,0 +1,13 @@
+count = 0
+total = 0.0
+done = False
+while not done:
+    count = count + 1
+    total = total + 0.5
+    done = count >= 100000
+
+print(count)
+print(total)
+print(-1125899906842623 - 1)
+print(1125899906842623)
+print(0.0 / 1.0 - 2.5)
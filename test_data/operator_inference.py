@@ -0,0 +1,17 @@
+def add(a, b):
+    return a + b
+
+
+print(add(1, 2.5))
+print(scale(2, 0.5))
+
+
+def scale(a, b):
+    return a * b
+
+
+def smaller(a, b):
+    return a < b
+
+
+print(smaller(3, 2.5))
@@ -0,0 +1,49 @@
+print(2 + 3 * 4)
+print((2 + 3) * 4)
+print(2 ** 3 ** 2)
+print(-2 ** 2)
+print(2.0 ** -1.0)
+print(7 / 2)
+print(7 // 2)
+print(-7 // 2)
+print(7 % 3)
+print(-7 % 3)
+print(7 % -3)
+print(7.5 // 2.0)
+print(-7.5 % 2.0)
+print(10 - 2 - 3)
+print(-(3 - 5))
+print(+4)
+print("con" + "cat")
+
+print(1 < 2 < 3)
+print(1 < 3 < 2)
+print(3 > 2 >= 2)
+print(1 == 1 != 2)
+print(1.5 <= 1.5)
+print("abc" < "abd")
+print(True == False)
+
+print(not True)
+print(not 0)
+print(True and False)
+print(False or True)
+print(1 and 2)
+print(0 or 5)
+print(not 1 == 2)
+print(1 < 2 and 2 < 3 or False)
+
+def noisy(x: bool) -> bool:
+    print("evaluated")
+    return x
+
+print(False and noisy(True))
+print(True or noisy(False))
+print(True and noisy(False))
+
+def loud(x: int) -> int:
+    print(x)
+    return x
+
+print(loud(1) < loud(2) < loud(3))
+print(loud(3) < loud(2) < loud(1))
@@ -0,0 +1,12 @@
+x: int = 1 +
+
+
+def f(a: int b: int) -> int:
+    return a
+
+
+print(x)
+
+
+y = 2 $ 3
+print(y)
@@ -0,0 +1,15 @@
+def widen(n: int) -> float:
+    return n  # lars: disable=implicit-cast
+
+
+# lars: disable=implicit-cast, type-error
+total: float = 1
+def broken(a: int) -> int:
+    b: int = "not an int"
+    return a
+# lars: enable=implicit-cast
+
+count: float = 2
+print(widen(3))
+print(total + count)
+print(broken(4))
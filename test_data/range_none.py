@@ -0,0 +1,7 @@
+def count(n: int):
+    for i in range(n):
+        print(i)
+
+
+count(2)
+count(None)
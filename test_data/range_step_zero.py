@@ -0,0 +1,7 @@
+def count(step: int):
+    for i in range(0, 10, step):
+        print(i)
+
+
+count(5)
+count(0)
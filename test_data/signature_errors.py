@@ -0,0 +1,16 @@
+def unused(x):
+    pass
+
+
+def both(y):
+    print(y)
+
+
+def identity(z):
+    return z
+
+
+both(1)
+both("one")
+print(identity(1))
+print(identity(2.5))
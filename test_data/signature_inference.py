@@ -0,0 +1,25 @@
+def add(a, b):
+    return a + b
+
+
+def greet(name):
+    print("hello " + name)
+
+
+def half(x):
+    return x / 2.0
+
+
+def big(n) -> bool:
+    return n > 100
+
+
+def twice(f: float):
+    return half(f) * 4.0
+
+
+print(add(1, 2))
+greet("lars")
+print(half(3.0))
+print(big(add(60, 50)))
+print(twice(1.5))
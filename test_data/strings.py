@@ -0,0 +1,8 @@
+greeting: str = "Hello, world!"
+print(greeting)
+print("tab\tseparated")
+print("quote: \"hi\"")
+print("")
+
+if "non-empty":
+    print("strings are truthy")
@@ -0,0 +1,8 @@
+def down(a: int) -> int:
+    if a:
+        return down(a - 1) + 1
+    else:
+        return 1 // a
+
+
+print(down(3))
@@ -0,0 +1,13 @@
+y: int = "not an int"
+
+
+def g(a: int) -> int:
+    return a + "one"
+
+
+print(undefined)
+print(g(1))
+
+inferred = 1
+inferred = "a string"
+nothing = None